@@ -0,0 +1,145 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    lexer::{tokenize, LexError},
+    state::is_valid_name,
+    token::{Token, Word, WordPart},
+};
+
+// この後ろに続く単語もコマンド名として扱う予約語
+const COMMAND_PREFIXES: [&str; 9] = [
+    "!", "{", "if", "then", "else", "elif", "do", "while", "until",
+];
+
+// expand_aliasesで処理待ちのトークン
+enum Item {
+    // 中身のVecはそのトークンを作ったエイリアス. 同じエイリアスを再帰的に展開しないように覚えておく
+    Token(Token, Vec<String>),
+    // 値が空白で終わるエイリアスの後ろ. 次の単語もエイリアスとして展開できる
+    CommandPosition,
+}
+
+// コマンド名の位置にある単語がエイリアスであれば, その値をトークンに区切ったものに置き換える
+// 置き換えた結果の先頭もエイリアスであれば続けて展開するが, 展開中のエイリアス自身は展開しない
+// `alias ls='ls -F'`のような定義でも止まらなくならない
+pub fn expand_aliases(
+    tokens: Vec<Token>,
+    aliases: &HashMap<String, String>,
+) -> Result<Vec<Token>, LexError> {
+    if aliases.is_empty() {
+        return Ok(tokens);
+    }
+    let mut input: VecDeque<Item> = tokens
+        .into_iter()
+        .map(|token| Item::Token(token, Vec::new()))
+        .collect();
+    let mut output = Vec::new();
+    let mut command_position = true;
+    // リダイレクト先のファイル名はコマンド名ではない
+    let mut redirect_target = false;
+
+    while let Some(item) = input.pop_front() {
+        let (token, origins) = match item {
+            Item::Token(token, origins) => (token, origins),
+            Item::CommandPosition => {
+                command_position = true;
+                continue;
+            }
+        };
+        match &token {
+            Token::Word(_) | Token::HereDoc { .. } if redirect_target => {
+                redirect_target = false;
+            }
+            Token::Word(word) if command_position => {
+                let name = word.as_literal().unwrap_or_default();
+                let expanding = origins.iter().any(|origin| origin == name);
+                if let Some(value) = aliases.get(name).filter(|_| !expanding) {
+                    let mut origins = origins.clone();
+                    origins.push(name.to_string());
+                    if value.ends_with([' ', '\t']) {
+                        input.push_front(Item::CommandPosition);
+                    }
+                    for token in tokenize(value)?.into_iter().rev() {
+                        input.push_front(Item::Token(token, origins.clone()));
+                    }
+                    continue;
+                }
+                command_position = COMMAND_PREFIXES.contains(&name) || is_assignment(word);
+            }
+            Token::Word(_) | Token::HereDoc { .. } => {}
+            Token::Redirect(..) => redirect_target = true,
+            // `;;`の後ろはcaseのパターンなのでコマンド名ではない
+            Token::DSemi => command_position = false,
+            Token::Pipe
+            | Token::AndIf
+            | Token::OrIf
+            | Token::Semi
+            | Token::Amp
+            | Token::Newline
+            | Token::LParen
+            | Token::RParen => command_position = true,
+        }
+        output.push(token);
+    }
+    Ok(output)
+}
+
+// `FOO=1 ll`のように代入の後ろに続く単語もコマンド名として扱う
+fn is_assignment(word: &Word) -> bool {
+    match word.parts.first() {
+        Some(WordPart::Literal(s)) => s
+            .split_once('=')
+            .is_some_and(|(name, _)| is_valid_name(name)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: &str, aliases: &[(&str, &str)]) -> String {
+        let aliases = aliases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let tokens = expand_aliases(tokenize(input).unwrap(), &aliases).unwrap();
+        let words: Vec<String> = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.unquote(),
+                token => token.to_string(),
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    #[test]
+    fn test_expand_aliases() {
+        let aliases = [
+            ("ll", "ls -l"),
+            ("ls", "ls -F"),
+            ("sudo", "sudo "),
+            ("loop", "loop2"),
+            ("loop2", "loop"),
+            ("count", "wc -l |"),
+        ];
+        assert_eq!(expand("ll src", &aliases), "ls -F -l src");
+        assert_eq!(
+            expand("echo ll; ll | ll", &aliases),
+            "echo ll ; ls -F -l | ls -F -l"
+        );
+        assert_eq!(expand("'ll' \\ll", &aliases), "ll ll");
+        assert_eq!(expand("sudo ll", &aliases), "sudo ls -F -l");
+        assert_eq!(expand("loop", &aliases), "loop");
+        assert_eq!(expand("count cat", &aliases), "wc -l | cat");
+        assert_eq!(
+            expand("X=1 ll > ll && if ll; then ll; fi", &aliases),
+            "X=1 ls -F -l > ll && if ls -F -l ; then ls -F -l ; fi"
+        );
+        assert_eq!(
+            expand("case x in ll) ll;; ll) :;; esac", &aliases),
+            "case x in ll ) ls -F -l ;; ll ) : ;; esac"
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use std::fmt::Display;
+
+use crate::state::ShellState;
+
+// 変数の値を式として評価する時の再帰の上限. `a=a`のような循環参照で止まらなくならないように
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ArithError {
+    // `1 +`のように値が来るべき場所に何も無い. 中身はその位置にあった文字列
+    OperandExpected(String),
+    // `1 2`のように演算子が来るべき場所に別のものがある
+    Syntax(String),
+    // `08`や`1a`のような不正な数値
+    InvalidNumber(String),
+    DivisionByZero,
+    NegativeExponent,
+    // `1 = 2`のように変数以外に代入しようとした
+    NotAssignable,
+    RecursionLimit,
+}
+
+impl Display for ArithError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArithError::OperandExpected(token) => write!(
+                f,
+                "syntax error: operand expected (error token is \"{}\")",
+                token
+            ),
+            ArithError::Syntax(token) => {
+                write!(
+                    f,
+                    "syntax error in expression (error token is \"{}\")",
+                    token
+                )
+            }
+            ArithError::InvalidNumber(token) => {
+                write!(f, "value too great for base (error token is \"{}\")", token)
+            }
+            ArithError::DivisionByZero => write!(f, "division by 0"),
+            ArithError::NegativeExponent => write!(f, "exponent less than 0"),
+            ArithError::NotAssignable => write!(f, "attempted assignment to non-variable"),
+            ArithError::RecursionLimit => write!(f, "expression recursion level exceeded"),
+        }
+    }
+}
+
+// `$(( ))`の中身やletの引数を整数の式として評価する. 空の式は0になる
+// 値は64bit整数で, オーバーフローした場合は折り返す
+pub fn evaluate(state: &mut ShellState, input: &str) -> Result<i64, ArithError> {
+    evaluate_at(state, input, 0)
+}
+
+fn evaluate_at(state: &mut ShellState, input: &str, depth: usize) -> Result<i64, ArithError> {
+    if depth > MAX_DEPTH {
+        return Err(ArithError::RecursionLimit);
+    }
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.parse_expr(0)?;
+    if parser.peek().is_some() {
+        return Err(ArithError::Syntax(parser.rest()));
+    }
+    Evaluator { state, depth }.eval(&expr)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+        }
+    }
+}
+
+// 長いものから順に並べておき, 先頭から一致するものを探す
+const OPERATORS: [&str; 36] = [
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", "=",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ArithError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let len = chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count();
+            let word: String = chars[i..i + len].iter().collect();
+            i += len;
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(parse_number(&word)?));
+            } else {
+                tokens.push(Token::Ident(word));
+            }
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Op(","));
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+                return Err(ArithError::Syntax(rest));
+            };
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// 0xで始まれば16進数, 0で始まれば8進数として読む
+fn parse_number(word: &str) -> Result<i64, ArithError> {
+    let (digits, radix) =
+        if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+            (hex, 16)
+        } else if word.len() > 1 && word.starts_with('0') {
+            (&word[1..], 8)
+        } else {
+            (word, 10)
+        };
+    // 大きすぎる値は64bitに収まるように折り返す
+    digits
+        .chars()
+        .try_fold(0i64, |n, c| {
+            let digit = c.to_digit(radix)?;
+            Some(n.wrapping_mul(radix as i64).wrapping_add(digit as i64))
+        })
+        .filter(|_| !digits.is_empty())
+        .ok_or_else(|| ArithError::InvalidNumber(word.to_string()))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BinaryOp {
+    Comma,
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Expr {
+    Number(i64),
+    Var(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    // cond ? then : else
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    // name = value, name += valueなど. opは複合代入の演算子
+    Assign(String, Option<BinaryOp>, Box<Expr>),
+    // ++name, name--など. prefixがtrueなら更新後の値, falseなら更新前の値になる
+    IncDec {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+// 二項演算子の結合力. 左の値が小さいほど優先順位が低い. 右の方が小さいものは右結合
+fn binary_op(op: &str) -> Option<(BinaryOp, u8, u8)> {
+    let (op, left, right) = match op {
+        "," => (BinaryOp::Comma, 1, 2),
+        "||" => (BinaryOp::Or, 7, 8),
+        "&&" => (BinaryOp::And, 9, 10),
+        "|" => (BinaryOp::BitOr, 11, 12),
+        "^" => (BinaryOp::BitXor, 13, 14),
+        "&" => (BinaryOp::BitAnd, 15, 16),
+        "==" => (BinaryOp::Eq, 17, 18),
+        "!=" => (BinaryOp::Ne, 17, 18),
+        "<" => (BinaryOp::Lt, 19, 20),
+        "<=" => (BinaryOp::Le, 19, 20),
+        ">" => (BinaryOp::Gt, 19, 20),
+        ">=" => (BinaryOp::Ge, 19, 20),
+        "<<" => (BinaryOp::Shl, 21, 22),
+        ">>" => (BinaryOp::Shr, 21, 22),
+        "+" => (BinaryOp::Add, 23, 24),
+        "-" => (BinaryOp::Sub, 23, 24),
+        "*" => (BinaryOp::Mul, 25, 26),
+        "/" => (BinaryOp::Div, 25, 26),
+        "%" => (BinaryOp::Rem, 25, 26),
+        "**" => (BinaryOp::Pow, 28, 27),
+        _ => return None,
+    };
+    Some((op, left, right))
+}
+
+// 代入(右結合)と三項演算子の結合力
+const ASSIGN_POWER: (u8, u8) = (4, 3);
+const CONDITIONAL_POWER: (u8, u8) = (6, 5);
+// 単項演算子は**より強く結合するので, -2**2は(-2)**2になる
+const UNARY_POWER: u8 = 29;
+
+fn assign_op(op: &str) -> Option<Option<BinaryOp>> {
+    if op == "=" {
+        return Some(None);
+    }
+    let op = op.strip_suffix('=')?;
+    match binary_op(op) {
+        Some((op, ..)) if !matches!(op, BinaryOp::Lt | BinaryOp::Gt) => Some(Some(op)),
+        _ => None,
+    }
+}
+
+// Pratt parser. 演算子の結合力を比べながら左から順に式を組み立てる
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // エラーメッセージ用に, 今の位置から後ろのトークンを文字列に戻す
+    fn rest(&self) -> String {
+        let rest: Vec<String> = self.tokens[self.pos..]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        rest.join(" ")
+    }
+
+    fn parse_expr(&mut self, min_power: u8) -> Result<Expr, ArithError> {
+        let mut left = self.parse_prefix()?;
+
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if op == "?" {
+                let (left_power, right_power) = CONDITIONAL_POWER;
+                if left_power < min_power {
+                    break;
+                }
+                self.next();
+                let then = self.parse_expr(0)?;
+                match self.next() {
+                    Some(Token::Op(":")) => {}
+                    Some(token) => return Err(ArithError::Syntax(token.to_string())),
+                    None => return Err(ArithError::OperandExpected(String::new())),
+                }
+                let otherwise = self.parse_expr(right_power)?;
+                left = Expr::Conditional(Box::new(left), Box::new(then), Box::new(otherwise));
+            } else if let Some(compound) = assign_op(op) {
+                let (left_power, right_power) = ASSIGN_POWER;
+                if left_power < min_power {
+                    break;
+                }
+                let Expr::Var(name) = left else {
+                    return Err(ArithError::NotAssignable);
+                };
+                self.next();
+                let value = self.parse_expr(right_power)?;
+                left = Expr::Assign(name, compound, Box::new(value));
+            } else if let Some((binary, left_power, right_power)) = binary_op(op) {
+                if left_power < min_power {
+                    break;
+                }
+                self.next();
+                let right = self.parse_expr(right_power)?;
+                left = Expr::Binary(binary, Box::new(left), Box::new(right));
+            } else {
+                break;
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expr, ArithError> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => return Err(ArithError::OperandExpected(String::new())),
+        };
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(name) => match self.peek() {
+                Some(Token::Op(op @ ("++" | "--"))) => {
+                    let delta = if *op == "++" { 1 } else { -1 };
+                    self.next();
+                    Ok(Expr::IncDec {
+                        name,
+                        delta,
+                        prefix: false,
+                    })
+                }
+                _ => Ok(Expr::Var(name)),
+            },
+            Token::LParen => {
+                let expr = self.parse_expr(0)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    Some(token) => Err(ArithError::Syntax(token.to_string())),
+                    None => Err(ArithError::Syntax("(".to_string())),
+                }
+            }
+            Token::Op(op @ ("++" | "--")) => {
+                let delta = if op == "++" { 1 } else { -1 };
+                match self.next() {
+                    Some(Token::Ident(name)) => Ok(Expr::IncDec {
+                        name,
+                        delta,
+                        prefix: true,
+                    }),
+                    // `--5`のように変数以外に付いている場合は単項演算子を2回適用したものとして扱う
+                    _ => {
+                        self.pos -= 1;
+                        let operand = self.parse_expr(UNARY_POWER)?;
+                        let unary = if op == "++" {
+                            UnaryOp::Plus
+                        } else {
+                            UnaryOp::Neg
+                        };
+                        Ok(Expr::Unary(
+                            unary,
+                            Box::new(Expr::Unary(unary, Box::new(operand))),
+                        ))
+                    }
+                }
+            }
+            Token::Op(op @ ("-" | "+" | "!" | "~")) => {
+                let unary = match op {
+                    "-" => UnaryOp::Neg,
+                    "+" => UnaryOp::Plus,
+                    "!" => UnaryOp::Not,
+                    _ => UnaryOp::BitNot,
+                };
+                let operand = self.parse_expr(UNARY_POWER)?;
+                Ok(Expr::Unary(unary, Box::new(operand)))
+            }
+            _ => {
+                self.pos -= 1;
+                Err(ArithError::OperandExpected(self.rest()))
+            }
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    state: &'a mut ShellState,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Var(name) => self.var(name),
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Plus => value,
+                    UnaryOp::Not => i64::from(value == 0),
+                    UnaryOp::BitNot => !value,
+                })
+            }
+            // &&と||は左辺だけで結果が決まる場合は右辺を評価しない. 右辺の代入なども行われない
+            Expr::Binary(BinaryOp::And, left, right) => {
+                Ok(i64::from(self.eval(left)? != 0 && self.eval(right)? != 0))
+            }
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                Ok(i64::from(self.eval(left)? != 0 || self.eval(right)? != 0))
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                apply(*op, left, right)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Assign(name, op, value) => {
+                let mut value = self.eval(value)?;
+                if let Some(op) = op {
+                    value = apply(*op, self.var(name)?, value)?;
+                }
+                self.state.set_var(name, &value.to_string());
+                Ok(value)
+            }
+            Expr::IncDec {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = self.var(name)?;
+                let new = old.wrapping_add(*delta);
+                self.state.set_var(name, &new.to_string());
+                Ok(if *prefix { new } else { old })
+            }
+        }
+    }
+
+    // 未設定や空の変数は0. 値が数値でなければ, その値自体を式として評価する
+    fn var(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.state.var(name).unwrap_or_default().trim().to_string();
+        if let Ok(n) = value.parse() {
+            return Ok(n);
+        }
+        evaluate_at(self.state, &value, self.depth + 1)
+    }
+}
+
+fn apply(op: BinaryOp, left: i64, right: i64) -> Result<i64, ArithError> {
+    let value = match op {
+        BinaryOp::Comma => right,
+        BinaryOp::Or => i64::from(left != 0 || right != 0),
+        BinaryOp::And => i64::from(left != 0 && right != 0),
+        BinaryOp::BitOr => left | right,
+        BinaryOp::BitXor => left ^ right,
+        BinaryOp::BitAnd => left & right,
+        BinaryOp::Eq => i64::from(left == right),
+        BinaryOp::Ne => i64::from(left != right),
+        BinaryOp::Lt => i64::from(left < right),
+        BinaryOp::Le => i64::from(left <= right),
+        BinaryOp::Gt => i64::from(left > right),
+        BinaryOp::Ge => i64::from(left >= right),
+        BinaryOp::Shl => left.wrapping_shl(right as u32),
+        BinaryOp::Shr => left.wrapping_shr(right as u32),
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Sub => left.wrapping_sub(right),
+        BinaryOp::Mul => left.wrapping_mul(right),
+        BinaryOp::Div | BinaryOp::Rem if right == 0 => return Err(ArithError::DivisionByZero),
+        BinaryOp::Div => left.wrapping_div(right),
+        BinaryOp::Rem => left.wrapping_rem(right),
+        BinaryOp::Pow if right < 0 => return Err(ArithError::NegativeExponent),
+        BinaryOp::Pow => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(state: &mut ShellState, input: &str) -> Result<i64, ArithError> {
+        evaluate(state, input)
+    }
+
+    #[test]
+    fn test_evaluate_operators() {
+        let mut state = ShellState::default();
+        let mut eval = |input: &str| eval(&mut state, input).unwrap();
+        assert_eq!(eval(""), 0);
+        assert_eq!(eval("1 + 2 * 3"), 7);
+        assert_eq!(eval("(1 + 2) * 3"), 9);
+        assert_eq!(eval("7 / 2 + 7 % 2"), 4);
+        assert_eq!(eval("-7 / 2"), -3);
+        assert_eq!(eval("2 ** 3 ** 2"), 512);
+        assert_eq!(eval("-2 ** 2"), 4);
+        assert_eq!(eval("1 << 4 | 3 & 1 ^ 2"), 19);
+        assert_eq!(eval("!0 + !5 + ~0"), 0);
+        assert_eq!(eval("3 > 2 && 2 >= 2 || 0"), 1);
+        assert_eq!(eval("1 == 2 ? 10 : 2 != 2 ? 20 : 30"), 30);
+        assert_eq!(eval("0x1f + 010 + 0"), 39);
+        assert_eq!(eval("--5"), 5);
+        assert_eq!(eval("1, 2, 3"), 3);
+        assert_eq!(eval("9223372036854775807 + 1"), i64::MIN);
+    }
+
+    #[test]
+    fn test_evaluate_variables() {
+        let mut state = ShellState::default();
+        state.set_var("x", "5");
+        state.set_var("expr", "x * 2");
+        let mut eval = |input: &str| eval(&mut state, input).unwrap();
+        assert_eq!(eval("x + unset_var"), 5);
+        assert_eq!(eval("expr + 1"), 11);
+        assert_eq!(eval("y = x += 3"), 8);
+        assert_eq!(eval("x++ + ++x"), 18);
+        assert_eq!(eval("x--"), 10);
+        assert_eq!(eval("y <<= 1, y"), 16);
+        // 評価されない側の代入は行われない
+        assert_eq!(eval("0 && (z = 1)"), 0);
+        assert_eq!(eval("1 ? 2 : (z = 1)"), 2);
+        assert_eq!(state.var("x"), Some("9"));
+        assert_eq!(state.var("y"), Some("16"));
+        assert_eq!(state.var("z"), None);
+    }
+
+    #[test]
+    fn test_evaluate_error() {
+        let mut state = ShellState::default();
+        state.set_var("loop", "loop + 1");
+        let mut eval = |input: &str| eval(&mut state, input).unwrap_err();
+        assert_eq!(eval("1 +"), ArithError::OperandExpected(String::new()));
+        assert_eq!(
+            eval("1 + * 2"),
+            ArithError::OperandExpected("* 2".to_string())
+        );
+        assert_eq!(eval("1 2"), ArithError::Syntax("2".to_string()));
+        assert_eq!(eval("(1 + 2"), ArithError::Syntax("(".to_string()));
+        assert_eq!(eval("08"), ArithError::InvalidNumber("08".to_string()));
+        assert_eq!(eval("1 / 0"), ArithError::DivisionByZero);
+        assert_eq!(eval("2 ** -1"), ArithError::NegativeExponent);
+        assert_eq!(eval("1 = 2"), ArithError::NotAssignable);
+        assert_eq!(eval("loop"), ArithError::RecursionLimit);
+        assert_eq!(eval("1 $ 2"), ArithError::Syntax("$ 2".to_string()));
+    }
+}
@@ -0,0 +1,294 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::token::Word;
+
+// `;`や改行で区切られたコマンドの列
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct List {
+    pub and_ors: Vec<AndOr>,
+}
+
+// `make && ./a.out || echo failed`のように&&や||で繋がれたパイプラインの列
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    // 末尾に&が付いていればバックグラウンドで実行する
+    pub background: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Connector {
+    // &&. 直前のパイプラインが成功した場合だけ実行する
+    And,
+    // ||. 直前のパイプラインが失敗した場合だけ実行する
+    Or,
+}
+
+// `cat file.txt | grep something`のように"|"で繋がれたコマンドの列
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    // `! cmd`. 終了ステータスの成功と失敗を反転する
+    pub negated: bool,
+}
+
+// パイプラインの各段に書けるコマンド
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    // ifやwhileなどの複合コマンド. `done > out`のように後ろにリダイレクトを書ける
+    Compound(CompoundCommand, Vec<Redirect>),
+    // name() { ... }
+    Function(FunctionDef),
+}
+
+// 関数の定義. 呼び出すたびに本体を実行するので, 定義した時点ではシェルの状態に登録するだけ
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionDef {
+    pub name: String,
+    // 呼び出しのたびにコピーしなくて済むようにRcで持つ
+    pub body: Rc<CompoundCommand>,
+    // `f() { ...; } > out`のリダイレクトは呼び出すたびに適用する
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompoundCommand {
+    // if c1; then b1; elif c2; then b2; else b3; fi. conditionsは(c1, b1), (c2, b2)の順に並ぶ
+    If {
+        conditions: Vec<(List, List)>,
+        else_body: Option<List>,
+    },
+    // while c; do b; done. untilの場合はcが失敗している間だけ繰り返す
+    While {
+        condition: List,
+        body: List,
+        until: bool,
+    },
+    // for name in words; do b; done. inが省略された場合(wordsがNone)は"$@"を使う
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    // case word in pattern1 | pattern2) b;; esac
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+    },
+    // { list; }. サブシェルを作らずにシェル自身の中でまとめて実行する
+    Group(List),
+    // ( list ). サブシェルで実行するので, 中での変数やカレントディレクトリの変更は外に残らない
+    Subshell(List),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SimpleCommand {
+    // `FOO=1 BAR=2 cmd`のようにコマンド名の前に書かれた代入
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Redirect {
+    // リダイレクト対象のfd. `2>err`なら2, `>out`なら1
+    pub fd: u32,
+    pub kind: RedirectKind,
+    pub target: Word,
+    // ヒアドキュメントの本文. targetには区切り文字が入る. それ以外のリダイレクトではNone
+    pub body: Option<Word>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RedirectKind {
+    // fd < file
+    Input,
+    // fd > file
+    Output,
+    // fd >> file
+    Append,
+    // fd >& n. targetで指定されたfdを複製する
+    Duplicate,
+    // fd <& n. 複製するのはDuplicateと同じで, 表示する時のために向きを区別しておく
+    DuplicateInput,
+    // &> file. 標準出力と標準エラー出力の両方をfileに書き込む
+    OutputAll,
+    // &>> file
+    AppendAll,
+    // fd << delimiter. bodyを標準入力に流し込む
+    HereDoc,
+    // fd <<- delimiter. 各行の先頭のタブはレキサーが取り除いてbodyに入れてある
+    HereDocStripTabs,
+    // fd <<< word. wordの後ろに改行を付けたものを標準入力に流し込む
+    HereString,
+}
+
+// jobsなどでコマンドを表示するために, 入力された形に近い文字列に戻せるようにしておく
+// 複合コマンドの中の改行は`;`にして1行で表示する
+impl Display for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, and_or) in self.and_ors.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            let terminator = if and_or.background { "&" } else { ";" };
+            write!(f, "{}{}", and_or, terminator)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for AndOr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && {}", pipeline)?,
+                Connector::Or => write!(f, " || {}", pipeline)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        let commands: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Simple(command) => write!(f, "{}", command),
+            Command::Compound(command, redirects) => {
+                write!(f, "{}", command)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+            Command::Function(function) => write!(f, "{}", function),
+        }
+    }
+}
+
+impl Display for FunctionDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}() {}", self.name, self.body)?;
+        for redirect in &self.redirects {
+            write!(f, " {}", redirect)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CompoundCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompoundCommand::If {
+                conditions,
+                else_body,
+            } => {
+                for (i, (condition, body)) in conditions.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { " elif" };
+                    write!(f, "{} {} then {}", keyword, condition, body)?;
+                }
+                if let Some(body) = else_body {
+                    write!(f, " else {}", body)?;
+                }
+                write!(f, " fi")
+            }
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{} {} do {} done", keyword, condition, body)
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {} done", body)
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in", word)?;
+                for item in items {
+                    let patterns: Vec<String> =
+                        item.patterns.iter().map(|p| p.to_string()).collect();
+                    // 本体の最後の`;`は`;;`と重なるので取り除く
+                    let body = item.body.to_string();
+                    let body = body.strip_suffix(';').unwrap_or(&body);
+                    write!(f, " {}) {};;", patterns.join(" | "), body)?;
+                }
+                write!(f, " esac")
+            }
+            CompoundCommand::Group(list) => write!(f, "{{ {} }}", list),
+            CompoundCommand::Subshell(list) => write!(f, "( {} )", list),
+        }
+    }
+}
+
+impl Display for SimpleCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let assignments = self.assignments.iter().map(|a| a.to_string());
+        let words = self.words.iter().map(|word| word.to_string());
+        let redirects = self.redirects.iter().map(|redirect| redirect.to_string());
+        let parts: Vec<String> = assignments.chain(words).chain(redirects).collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            RedirectKind::Input if self.fd == 0 => write!(f, "<{}", self.target),
+            RedirectKind::Input => write!(f, "{}<{}", self.fd, self.target),
+            RedirectKind::Output if self.fd == 1 => write!(f, ">{}", self.target),
+            RedirectKind::Output => write!(f, "{}>{}", self.fd, self.target),
+            RedirectKind::Append if self.fd == 1 => write!(f, ">>{}", self.target),
+            RedirectKind::Append => write!(f, "{}>>{}", self.fd, self.target),
+            RedirectKind::Duplicate => write!(f, "{}>&{}", self.fd, self.target),
+            RedirectKind::DuplicateInput if self.fd == 0 => write!(f, "<&{}", self.target),
+            RedirectKind::DuplicateInput => write!(f, "{}<&{}", self.fd, self.target),
+            RedirectKind::OutputAll => write!(f, "&>{}", self.target),
+            RedirectKind::AppendAll => write!(f, "&>>{}", self.target),
+            RedirectKind::HereDoc if self.fd == 0 => write!(f, "<<{}", self.target),
+            RedirectKind::HereDoc => write!(f, "{}<<{}", self.fd, self.target),
+            RedirectKind::HereDocStripTabs if self.fd == 0 => write!(f, "<<-{}", self.target),
+            RedirectKind::HereDocStripTabs => write!(f, "{}<<-{}", self.fd, self.target),
+            RedirectKind::HereString if self.fd == 0 => write!(f, "<<<{}", self.target),
+            RedirectKind::HereString => write!(f, "{}<<<{}", self.fd, self.target),
+        }
+    }
+}
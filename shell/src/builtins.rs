@@ -0,0 +1,1580 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, IsTerminal, Write},
+    iter, mem,
+    os::{
+        fd::{AsRawFd, BorrowedFd},
+        unix::{fs::PermissionsExt, process::CommandExt},
+    },
+    path::{Component, Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, Signal},
+    unistd::{self, Pid},
+};
+
+use crate::{
+    arith, condition,
+    error::{describe, ShellError},
+    executor::{pipeline_status, wait_for_job},
+    expand::abbreviate_home,
+    input::Input,
+    printf, repl,
+    signal::{init_interactive_signals, restore_default_signals, take_interrupted},
+    state::{is_valid_name, Flow, ShellState},
+};
+
+type Builtin = fn(&mut ShellState, &[String], &mut Stdio) -> io::Result<i32>;
+
+// ビルトインコマンドの名前と実装
+const BUILTINS: [(&str, Builtin); 37] = [
+    ("cd", cd),
+    ("pushd", pushd),
+    ("popd", |state, _, stdio| popd(state, stdio)),
+    ("dirs", dirs),
+    ("pwd", pwd),
+    ("exit", exit),
+    ("set", set),
+    ("export", export),
+    ("unset", unset),
+    ("shift", shift),
+    ("jobs", jobs),
+    ("fg", fg),
+    ("bg", bg),
+    ("wait", wait),
+    ("kill", kill_builtin),
+    ("env", |state, _, stdio| env_builtin(state, stdio)),
+    ("history", history),
+    ("break", |state, args, stdio| {
+        loop_control(state, "break", args, stdio)
+    }),
+    ("continue", |state, args, stdio| {
+        loop_control(state, "continue", args, stdio)
+    }),
+    ("local", local),
+    ("return", return_builtin),
+    ("let", let_builtin),
+    ("alias", alias),
+    ("unalias", unalias),
+    ("echo", |_, args, stdio| echo(args, stdio)),
+    ("printf", printf_builtin),
+    ("true", |_, _, _| Ok(0)),
+    ("false", |_, _, _| Ok(1)),
+    (":", |_, _, _| Ok(0)),
+    ("test", |state, args, stdio| {
+        test(state, "test", args, stdio)
+    }),
+    ("[", bracket),
+    ("type", type_builtin),
+    ("which", which),
+    ("source", source),
+    (".", source),
+    ("exec", exec),
+    ("read", read),
+];
+
+// typeで予約語として表示する単語
+const KEYWORDS: [&str; 16] = [
+    "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if", "in", "then",
+    "until", "while",
+];
+
+// ビルトインが読み書きする標準入出力. 外部コマンドと同じようにパイプやリダイレクトの先に繋がる
+// 普段はシェル自身のfd 0, 1, 2だが, テストではVec<u8>などに書かせて出力を確かめられる
+pub struct Stdio<'a> {
+    pub stdin: BorrowedFd<'a>,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+// envは引数が無い場合だけビルトインとして環境変数を表示する. `env FOO=1 cmd`のような使い方は外部コマンドに任せる
+pub fn is_builtin(name: &str, args: &[String]) -> bool {
+    names().any(|builtin| builtin == name) && (name != "env" || args.is_empty())
+}
+
+// nameがビルトインコマンドであれば実行して終了ステータスを返す. ビルトインでなければNoneを返す
+// 改行で終わらない出力もプロンプトより先に表示されるように, 最後に標準出力を吐き出す
+// パイプの読み手が先に終了した場合などの書き込みエラーは, 外部コマンドと同じく失敗として扱う
+pub fn run(state: &mut ShellState, name: &str, args: &[String], stdio: &mut Stdio) -> Option<i32> {
+    if !is_builtin(name, args) {
+        return None;
+    }
+    let (_, builtin) = BUILTINS.iter().find(|(builtin, _)| *builtin == name)?;
+    let result = builtin(state, args, stdio).and_then(|status| {
+        stdio.stdout.flush()?;
+        Ok(status)
+    });
+    Some(result.unwrap_or_else(|e| {
+        let _ = writeln!(stdio.stderr, "{}: write error: {}", name, e);
+        1
+    }))
+}
+
+// cdは子プロセスに実行させたところで親プロセスの状態は何も変わらないため, 親プロセス自体が見ているディレクトリを変更する
+// 引数が無ければ$HOMEに, `cd -`なら直前にいたディレクトリ($OLDPWD)に移動する
+fn cd(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let (target, print) = match args.first().map(|arg| arg.as_str()) {
+        None => match state.var("HOME") {
+            Some(home) => (home.to_string(), false),
+            None => {
+                writeln!(stdio.stderr, "cd: HOME not set")?;
+                return Ok(1);
+            }
+        },
+        Some("-") => match state.var("OLDPWD") {
+            Some(oldpwd) => (oldpwd.to_string(), true),
+            None => {
+                writeln!(stdio.stderr, "cd: OLDPWD not set")?;
+                return Ok(1);
+            }
+        },
+        Some(dir) => (dir.to_string(), false),
+    };
+
+    match change_dir(state, &target) {
+        Ok(found_in_cdpath) => {
+            if print || found_in_cdpath {
+                writeln!(stdio.stdout, "{}", state.var("PWD").unwrap_or_default())?;
+            }
+            Ok(0)
+        }
+        Err(e) => {
+            writeln!(stdio.stderr, "cd: {}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+// ディレクトリを移動してPWDとOLDPWDを更新する. CDPATHから見つけた場合はtrueを返す
+// PWDはシンボリックリンクを解決せずに, 辿ってきた通りのパスを保持する
+fn change_dir(state: &mut ShellState, target: &str) -> Result<bool, String> {
+    let old_dir = current_dir(state);
+    let (new_dir, found_in_cdpath) = match (search_cdpath(state, target), &old_dir) {
+        (Some(dir), _) => (dir, true),
+        (None, Ok(old_dir)) => (normalize_path(&Path::new(old_dir).join(target)), false),
+        // 絶対パスならカレントディレクトリが分からなくても移動できる
+        (None, Err(_)) if target.starts_with('/') => (normalize_path(Path::new(target)), false),
+        (None, Err(e)) => return Err(e.to_string()),
+    };
+
+    env::set_current_dir(&new_dir).map_err(|e| format!("{}: {}", target, describe(&e)))?;
+    if let Ok(old_dir) = old_dir {
+        state.export_var("OLDPWD", Some(&old_dir));
+    }
+    state.export_var("PWD", Some(&new_dir.display().to_string()));
+    Ok(found_in_cdpath)
+}
+
+// `cd src`のように/や.で始まらない相対パスの場合は, CDPATHに:区切りで書かれたディレクトリの中から探す
+fn search_cdpath(state: &ShellState, target: &str) -> Option<PathBuf> {
+    if target.starts_with('/') || target == "." || target == ".." {
+        return None;
+    }
+    if target.starts_with("./") || target.starts_with("../") {
+        return None;
+    }
+    let cdpath = state.var("CDPATH")?;
+    let cwd = current_dir(state).ok()?;
+    cdpath
+        .split(':')
+        // 空の要素はカレントディレクトリを表すが, その場合は普通にcdするのと同じなので見つけたことにはしない
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| normalize_path(&Path::new(&cwd).join(dir).join(target)))
+        .find(|dir| dir.is_dir())
+}
+
+// 現在のディレクトリ. $PWDが無ければ実際のカレントディレクトリを返す
+// ディレクトリが削除されているなどで取得できない場合はエラーにする
+pub fn current_dir(state: &ShellState) -> Result<String, ShellError> {
+    match state.var("PWD") {
+        Some(pwd) if pwd.starts_with('/') => Ok(pwd.to_string()),
+        _ => env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .map_err(ShellError::CurrentDir),
+    }
+}
+
+// .と..をパスの文字列上で解決する. `cd ..`でシンボリックリンクを辿る前のディレクトリに戻れるようにする
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// pushd dir: 今のディレクトリをスタックに積んでdirに移動する. 引数が無ければスタックの一番上と入れ替える
+fn pushd(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let target = match args.first() {
+        Some(dir) => dir.clone(),
+        None => match state.dir_stack.pop() {
+            Some(dir) => dir,
+            None => {
+                writeln!(stdio.stderr, "pushd: no other directory")?;
+                return Ok(1);
+            }
+        },
+    };
+
+    let old_dir = current_dir(state);
+    let result = old_dir
+        .as_ref()
+        .map_err(|e| e.to_string())
+        .and_then(|_| change_dir(state, &target));
+    if let Err(e) = result {
+        writeln!(stdio.stderr, "pushd: {}", e)?;
+        if args.is_empty() {
+            state.dir_stack.push(target);
+        }
+        return Ok(1);
+    }
+    state.dir_stack.extend(old_dir);
+    print_dirs(state, false, false, stdio)
+}
+
+// スタックの一番上のディレクトリを取り出してそこに移動する
+fn popd(state: &mut ShellState, stdio: &mut Stdio) -> io::Result<i32> {
+    let Some(dir) = state.dir_stack.pop() else {
+        writeln!(stdio.stderr, "popd: directory stack empty")?;
+        return Ok(1);
+    };
+    if let Err(e) = change_dir(state, &dir) {
+        writeln!(stdio.stderr, "popd: {}", e)?;
+        state.dir_stack.push(dir);
+        return Ok(1);
+    }
+    print_dirs(state, false, false, stdio)
+}
+
+// ディレクトリスタックを表示する. -cで空にし, -pで1行に1つずつ, -vで番号付きで表示する
+fn dirs(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let mut per_line = false;
+    let mut numbered = false;
+    for arg in args {
+        match arg.as_str() {
+            "-c" => {
+                state.dir_stack.clear();
+                return Ok(0);
+            }
+            "-p" => per_line = true,
+            "-v" => numbered = true,
+            _ => {
+                writeln!(stdio.stderr, "dirs: {}: invalid option", arg)?;
+                return Ok(2);
+            }
+        }
+    }
+    print_dirs(state, per_line, numbered, stdio)
+}
+
+fn print_dirs(
+    state: &ShellState,
+    per_line: bool,
+    numbered: bool,
+    stdio: &mut Stdio,
+) -> io::Result<i32> {
+    let cwd = match current_dir(state) {
+        Ok(cwd) => cwd,
+        Err(e) => {
+            writeln!(stdio.stderr, "dirs: {}", e)?;
+            return Ok(1);
+        }
+    };
+    let home = state.var("HOME").map(PathBuf::from);
+    let dirs: Vec<String> = std::iter::once(cwd)
+        .chain(state.dir_stack.iter().rev().cloned())
+        .map(|dir| match &home {
+            Some(home) => abbreviate_home(Path::new(&dir), home),
+            None => dir,
+        })
+        .collect();
+
+    if numbered {
+        for (i, dir) in dirs.iter().enumerate() {
+            writeln!(stdio.stdout, "{:>2}  {}", i, dir)?;
+        }
+    } else if per_line {
+        for dir in dirs {
+            writeln!(stdio.stdout, "{}", dir)?;
+        }
+    } else {
+        writeln!(stdio.stdout, "{}", dirs.join(" "))?;
+    }
+    Ok(0)
+}
+
+fn exit(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let status = match args.first() {
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(status) => status,
+            Err(_) => {
+                writeln!(stdio.stderr, "exit: {}: numeric argument required", arg)?;
+                2
+            }
+        },
+        None => state.last_status,
+    };
+    state.exit_status = Some(status);
+    Ok(status)
+}
+
+// `set -o pipefail`, `set +o pipefail`でオプションを切り替える. `set -o`だけの場合は現在の設定を表示する
+// `set -- a b c`や`set a b c`では, `--`かオプションでない最初の引数から後ろを$1, $2, ...にする
+// 引数が無い場合は全てのシェル変数を表示する
+fn set(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    if args.is_empty() {
+        let mut vars: Vec<(&String, &str)> = state
+            .vars
+            .iter()
+            .map(|(name, var)| (name, var.value.as_str()))
+            .collect();
+        vars.sort();
+        for (name, value) in vars {
+            writeln!(stdio.stdout, "{}={}", name, quote(value))?;
+        }
+        return Ok(0);
+    }
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let enable = match arg.as_str() {
+            "-o" => true,
+            "+o" => false,
+            "--" => {
+                state.positional = args.as_slice().to_vec();
+                return Ok(0);
+            }
+            _ if !arg.starts_with(['-', '+']) => {
+                state.positional = iter::once(arg).chain(args).cloned().collect();
+                return Ok(0);
+            }
+            _ => {
+                writeln!(stdio.stderr, "set: {}: invalid option", arg)?;
+                return Ok(2);
+            }
+        };
+        match args.next() {
+            Some(name) => {
+                if let Err(e) = state.options.set(name, enable) {
+                    writeln!(stdio.stderr, "set: {}", e)?;
+                    return Ok(2);
+                }
+            }
+            None => {
+                for (name, value) in state.options.list() {
+                    if enable {
+                        writeln!(
+                            stdio.stdout,
+                            "{:<15} {}",
+                            name,
+                            if value { "on" } else { "off" }
+                        )?;
+                    } else {
+                        writeln!(
+                            stdio.stdout,
+                            "set {}o {}",
+                            if value { "-" } else { "+" },
+                            name
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+// `export NAME=value`, `export NAME`で変数を子プロセスに引き継がれるようにする
+// 引数が無いか-pの場合はexportされている変数を一覧表示し, -nの場合はexportを解除する
+fn export(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let (unexport, names) = match args.first().map(|arg| arg.as_str()) {
+        Some("-n") => (true, &args[1..]),
+        Some("-p") | None => {
+            for (name, value) in state.exported_vars() {
+                writeln!(stdio.stdout, "export {}={}", name, quote(&value))?;
+            }
+            return Ok(0);
+        }
+        _ => (false, args),
+    };
+
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            writeln!(stdio.stderr, "export: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        if unexport {
+            if let Some(value) = value {
+                state.set_var(name, value);
+            }
+            state.unexport_var(name);
+        } else {
+            state.export_var(name, value);
+        }
+    }
+    Ok(status)
+}
+
+fn unset(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let names = match args.first().map(|arg| arg.as_str()) {
+        Some("-v") => &args[1..],
+        // `unset -f name`は関数を削除する
+        Some("-f") => {
+            for name in &args[1..] {
+                state.functions.remove(name);
+            }
+            return Ok(0);
+        }
+        _ => args,
+    };
+    let mut status = 0;
+    for name in names {
+        if !is_valid_name(name) {
+            writeln!(stdio.stderr, "unset: `{}': not a valid identifier", name)?;
+            status = 1;
+            continue;
+        }
+        state.unset_var(name);
+    }
+    Ok(status)
+}
+
+// alias name=value ... エイリアスを定義する. 値の無い名前はその定義を, 引数が無ければ全ての定義を表示する
+fn alias(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let print = |stdout: &mut dyn Write, name: &str, value: &str| {
+        writeln!(stdout, "alias {}={}", name, quote(value))
+    };
+    if args.is_empty() || args[0] == "-p" {
+        let mut aliases: Vec<_> = state.aliases.iter().collect();
+        aliases.sort();
+        for (name, value) in aliases {
+            print(stdio.stdout, name, value)?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if !is_valid_alias_name(name) {
+                    writeln!(stdio.stderr, "alias: `{}': invalid alias name", name)?;
+                    status = 1;
+                    continue;
+                }
+                state.aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match state.aliases.get(arg) {
+                Some(value) => print(stdio.stdout, arg, value)?,
+                None => {
+                    writeln!(stdio.stderr, "alias: {}: not found", arg)?;
+                    status = 1;
+                }
+            },
+        }
+    }
+    Ok(status)
+}
+
+// エイリアスの名前にはクォートやメタ文字, 展開に使う文字を含められない
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "/$`=\\'\"|&;<>()".contains(c))
+}
+
+// unalias name ... エイリアスを削除する. `unalias -a`で全て削除する
+fn unalias(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    if args.first().is_some_and(|arg| arg == "-a") {
+        state.aliases.clear();
+        return Ok(0);
+    }
+    if args.is_empty() {
+        writeln!(stdio.stderr, "unalias: usage: unalias [-a] name [name ...]")?;
+        return Ok(2);
+    }
+    let mut status = 0;
+    for name in args {
+        if state.aliases.remove(name).is_none() {
+            writeln!(stdio.stderr, "unalias: {}: not found", name)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+// 位置パラメータをn個(デフォルトは1個)左にずらす. $2が$1になる
+fn shift(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let count = match args.first().map(|arg| arg.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            writeln!(
+                stdio.stderr,
+                "shift: {}: numeric argument required",
+                args[0]
+            )?;
+            return Ok(2);
+        }
+    };
+    if count > state.positional.len() {
+        writeln!(stdio.stderr, "shift: shift count out of range")?;
+        return Ok(1);
+    }
+    state.positional.drain(..count);
+    Ok(0)
+}
+
+// break [n], continue [n]. 実際に抜けるのはループを実行している側で, ここでは何段抜けるかを記録するだけ
+fn loop_control(
+    state: &mut ShellState,
+    name: &str,
+    args: &[String],
+    stdio: &mut Stdio,
+) -> io::Result<i32> {
+    let count = match args.first().map(|arg| arg.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) if count > 0 => count,
+        Some(Ok(_)) => {
+            writeln!(
+                stdio.stderr,
+                "{}: {}: loop count out of range",
+                name, args[0]
+            )?;
+            return Ok(1);
+        }
+        Some(Err(_)) => {
+            writeln!(
+                stdio.stderr,
+                "{}: {}: numeric argument required",
+                name, args[0]
+            )?;
+            return Ok(2);
+        }
+    };
+    if state.loop_depth == 0 {
+        writeln!(
+            stdio.stderr,
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        )?;
+        return Ok(0);
+    }
+    let count = count.min(state.loop_depth);
+    state.flow = Some(if name == "break" {
+        Flow::Break(count)
+    } else {
+        Flow::Continue(count)
+    });
+    Ok(0)
+}
+
+// local name[=value] ... 関数から戻ると呼び出し前の値に戻る変数を作る
+fn local(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    if state.local_scopes.is_empty() {
+        writeln!(stdio.stderr, "local: can only be used in a function")?;
+        return Ok(1);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_valid_name(name) {
+            writeln!(stdio.stderr, "local: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        state.declare_local(name);
+        if let Some(value) = value {
+            state.set_var(name, value);
+        }
+    }
+    Ok(status)
+}
+
+// return [n]. nを省略した場合は直前のコマンドの終了ステータスで関数やsourceしたファイルから戻る
+fn return_builtin(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    if state.local_scopes.is_empty() && state.source_depth == 0 {
+        writeln!(
+            stdio.stderr,
+            "return: can only `return' from a function or sourced script"
+        )?;
+        return Ok(1);
+    }
+    let status = match args.first() {
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(status) => status,
+            Err(_) => {
+                writeln!(stdio.stderr, "return: {}: numeric argument required", arg)?;
+                2
+            }
+        },
+        None => state.last_status,
+    };
+    state.flow = Some(Flow::Return);
+    Ok(status)
+}
+
+// let expr ... 各引数を整数の式として評価する. 最後の式の値が0なら1, それ以外なら0を返す
+fn let_builtin(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    if args.is_empty() {
+        writeln!(stdio.stderr, "let: expression expected")?;
+        return Ok(1);
+    }
+    let mut value = 0;
+    for arg in args {
+        value = match arith::evaluate(state, arg) {
+            Ok(value) => value,
+            Err(e) => {
+                writeln!(stdio.stderr, "let: {}: {}", arg, e)?;
+                return Ok(1);
+            }
+        };
+    }
+    Ok(i32::from(value == 0))
+}
+
+// 子プロセスに渡される環境変数を表示する
+fn env_builtin(state: &ShellState, stdio: &mut Stdio) -> io::Result<i32> {
+    for (name, value) in state.exported_vars() {
+        writeln!(stdio.stdout, "{}={}", name, value)?;
+    }
+    Ok(0)
+}
+
+// pwd [-L | -P]. -Pの場合はシンボリックリンクを解決した実際のパスを表示する
+fn pwd(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let mut physical = false;
+    for arg in args {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            _ => {
+                writeln!(stdio.stderr, "pwd: {}: invalid option", arg)?;
+                return Ok(2);
+            }
+        }
+    }
+    let dir = if physical {
+        env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .map_err(ShellError::CurrentDir)
+    } else {
+        current_dir(state)
+    };
+    match dir {
+        Ok(dir) => {
+            writeln!(stdio.stdout, "{}", dir)?;
+            Ok(0)
+        }
+        Err(e) => {
+            writeln!(stdio.stderr, "pwd: {}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+// echo [-neE] args... 引数を空白区切りで表示する. -nで末尾の改行を出さず, -eでバックスラッシュのエスケープを解釈する
+// bashと同じく, -nや-neのように全ての文字がオプションとして正しい引数だけをオプションとして扱う
+fn echo(args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut args = args;
+    while let Some(flags) = args.first().and_then(|arg| arg.strip_prefix('-')) {
+        if flags.is_empty() || !flags.chars().all(|c| "neE".contains(c)) {
+            break;
+        }
+        for c in flags.chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        args = &args[1..];
+    }
+
+    let joined = args.join(" ");
+    let mut output = Vec::new();
+    if escapes {
+        // \cより後ろは改行も含めて何も出力しない
+        newline &= printf::expand_escapes(&joined, true, &mut output);
+    } else {
+        output.extend(joined.as_bytes());
+    }
+    if newline {
+        output.push(b'\n');
+    }
+    stdio.stdout.write_all(&output)?;
+    Ok(0)
+}
+
+// printf [-v name] format [args...]. -vの場合は出力せずに変数に代入する
+fn printf_builtin(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let (var, args) = match args.first().map(|arg| arg.as_str()) {
+        Some("-v") => match args.get(1) {
+            Some(name) => (Some(name), &args[2..]),
+            None => {
+                writeln!(stdio.stderr, "printf: -v: option requires an argument")?;
+                return Ok(2);
+            }
+        },
+        Some("--") => (None, &args[1..]),
+        _ => (None, args),
+    };
+    let Some((format, args)) = args.split_first() else {
+        writeln!(
+            stdio.stderr,
+            "printf: usage: printf [-v var] format [arguments]"
+        )?;
+        return Ok(2);
+    };
+    if let Some(name) = var.filter(|name| !is_valid_name(name)) {
+        writeln!(stdio.stderr, "printf: `{}': not a valid identifier", name)?;
+        return Ok(2);
+    }
+
+    let (output, errors) = printf::printf(format, args);
+    for e in &errors {
+        writeln!(stdio.stderr, "printf: {}", e)?;
+    }
+    match var {
+        Some(name) => state.set_var(name, &String::from_utf8_lossy(&output)),
+        None => stdio.stdout.write_all(&output)?,
+    }
+    Ok(i32::from(!errors.is_empty()))
+}
+
+// test expr. 条件が成り立てば0, 成り立たなければ1, 式が間違っていれば2を返す
+fn test(state: &mut ShellState, name: &str, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    match condition::evaluate(state, args) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => {
+            writeln!(stdio.stderr, "{}: {}", name, e)?;
+            Ok(2)
+        }
+    }
+}
+
+// [ expr ]. 最後の引数が]でなければならない以外はtestと同じ
+fn bracket(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    match args.split_last() {
+        Some((last, args)) if last == "]" => test(state, "[", args, stdio),
+        _ => {
+            writeln!(stdio.stderr, "[: missing `]'")?;
+            Ok(2)
+        }
+    }
+}
+
+// type [-t] name ... 名前がコマンドとしてどう解釈されるかを表示する
+// エイリアス, 予約語, 関数, ビルトイン, 外部コマンドの順に探す. -tの場合は種類を表す単語だけを表示する
+fn type_builtin(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let (terse, targets) = match args.first() {
+        Some(arg) if arg == "-t" => (true, &args[1..]),
+        _ => (false, args),
+    };
+
+    let mut status = 0;
+    for name in targets {
+        let (kind, description) = if let Some(value) = state.aliases.get(name) {
+            ("alias", format!("{} is aliased to `{}'", name, value))
+        } else if KEYWORDS.contains(&name.as_str()) {
+            ("keyword", format!("{} is a shell keyword", name))
+        } else if let Some(function) = state.functions.get(name) {
+            ("function", format!("{} is a function\n{}", name, function))
+        } else if names().any(|builtin| builtin == name) {
+            ("builtin", format!("{} is a shell builtin", name))
+        } else if let Some(path) = find_commands(state, name).first() {
+            ("file", format!("{} is {}", name, path.display()))
+        } else {
+            if !terse {
+                writeln!(stdio.stderr, "type: {}: not found", name)?;
+            }
+            status = 1;
+            continue;
+        };
+        if terse {
+            writeln!(stdio.stdout, "{}", kind)?;
+        } else {
+            writeln!(stdio.stdout, "{}", description)?;
+        }
+    }
+    Ok(status)
+}
+
+// which [-a] name ... $PATHから実行できるファイルを探してパスを表示する. -aの場合は見つかったもの全てを表示する
+fn which(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let (all, names) = match args.first() {
+        Some(arg) if arg == "-a" => (true, &args[1..]),
+        _ => (false, args),
+    };
+
+    let mut status = 0;
+    for name in names {
+        let found = find_commands(state, name);
+        if found.is_empty() {
+            status = 1;
+        }
+        for path in found.iter().take(if all { usize::MAX } else { 1 }) {
+            writeln!(stdio.stdout, "{}", path.display())?;
+        }
+    }
+    Ok(status)
+}
+
+// nameという名前の実行できるファイルを$PATHの順に探す. /を含む場合はそのパス自体が実行できるかだけを見る
+fn find_commands(state: &ShellState, name: &str) -> Vec<PathBuf> {
+    let is_executable = |path: &PathBuf| {
+        fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if name.contains('/') {
+        return Some(PathBuf::from(name))
+            .into_iter()
+            .filter(is_executable)
+            .collect();
+    }
+    if name.is_empty() {
+        return Vec::new();
+    }
+    state
+        .var("PATH")
+        .unwrap_or_default()
+        .split(':')
+        // 空の要素はカレントディレクトリを表す
+        .map(|dir| Path::new(if dir.is_empty() { "." } else { dir }).join(name))
+        .filter(is_executable)
+        .collect()
+}
+
+// source file [args...], . file [args...]. ファイルに書かれたコマンドを今のシェルの中で実行する
+// /を含まないファイル名は$PATHから探し, 見つからなければカレントディレクトリのものを使う
+// 引数があれば実行中だけ位置パラメータにする. ファイルの中でreturnすると残りを飛ばして戻る
+fn source(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let Some((name, args)) = args.split_first() else {
+        writeln!(stdio.stderr, "source: filename argument required")?;
+        return Ok(2);
+    };
+    let path = if name.contains('/') {
+        PathBuf::from(name)
+    } else {
+        state
+            .var("PATH")
+            .unwrap_or_default()
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join(name))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(name))
+    };
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            writeln!(stdio.stderr, "source: {}: {}", name, describe(&e))?;
+            return Ok(1);
+        }
+    };
+
+    let positional = (!args.is_empty()).then(|| mem::replace(&mut state.positional, args.to_vec()));
+    state.source_depth += 1;
+    // 何も実行しなかった場合は0
+    state.last_status = 0;
+    let mut input = Input::from_reader(Box::new(BufReader::new(file)));
+    if let Err(e) = repl::execute_input(state, &mut input) {
+        writeln!(stdio.stderr, "shell: {}: {}", path.display(), e)?;
+        state.last_status = e.status();
+    }
+    if state.flow == Some(Flow::Return) {
+        state.flow = None;
+    }
+    state.source_depth -= 1;
+    if let Some(positional) = positional {
+        state.positional = positional;
+    }
+    Ok(state.last_status)
+}
+
+// exec command [args...]. シェル自身をコマンドに置き換える. 戻ってくるのは起動できなかった場合だけ
+// コマンドが無い場合は何もしない. `exec > file`のリダイレクトはシェル自身に残す(executor側で行う)
+fn exec(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let Some((name, args)) = args.split_first() else {
+        return Ok(0);
+    };
+    let _ = stdio.stdout.flush();
+    // 無視しているシグナルはexecしても無視されたままになるので, デフォルトに戻してから置き換える
+    if state.terminal.is_some() {
+        let _ = restore_default_signals();
+    }
+    let error = Command::new(name)
+        .args(args)
+        .env_clear()
+        .envs(state.exported_vars())
+        .exec();
+    if state.terminal.is_some() {
+        init_interactive_signals();
+    }
+
+    let e = ShellError::Spawn {
+        command: name.clone(),
+        error,
+    };
+    writeln!(stdio.stderr, "exec: {}", e)?;
+    Ok(e.status())
+}
+
+// read [-r] [-p prompt] [-d delim] [name ...]. 標準入力から1行読み, $IFSで区切って順に変数に代入する
+// 最後の変数には残り全てを代入する. 変数名が無ければ$REPLYに行全体を代入する
+// -rが無ければバックスラッシュで次の文字をエスケープでき, 行末のバックスラッシュで次の行に続けられる
+// 区切り文字を読む前に入力が終わった場合は, 読めたところまでを代入して1を返す
+fn read(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let mut raw = false;
+    let mut prompt = None;
+    let mut delimiter = b'\n';
+    let mut names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" if names.is_empty() => raw = true,
+            "-p" | "-d" if names.is_empty() => {
+                let Some(value) = args.next() else {
+                    writeln!(stdio.stderr, "read: {}: option requires an argument", arg)?;
+                    return Ok(2);
+                };
+                if arg == "-p" {
+                    prompt = Some(value);
+                } else {
+                    // -d ''の場合はNUL文字まで読む
+                    delimiter = value.bytes().next().unwrap_or(0);
+                }
+            }
+            _ if names.is_empty() && arg.starts_with('-') && arg.len() > 1 => {
+                writeln!(stdio.stderr, "read: {}: invalid option", arg)?;
+                return Ok(2);
+            }
+            _ => names.push(arg.as_str()),
+        }
+    }
+    if let Some(name) = names.iter().find(|name| !is_valid_name(name)) {
+        writeln!(stdio.stderr, "read: `{}': not a valid identifier", name)?;
+        return Ok(1);
+    }
+
+    // プロンプトは端末から読む場合だけ表示する
+    if let Some(prompt) = prompt.filter(|_| stdio.stdin.is_terminal()) {
+        write!(stdio.stderr, "{}", prompt)?;
+    }
+    let (line, escaped, complete) = match read_input(stdio.stdin, delimiter, raw) {
+        Ok(input) => input,
+        Err(Errno::EINTR) => {
+            writeln!(stdio.stderr)?;
+            return Ok(128 + Signal::SIGINT as i32);
+        }
+        Err(e) => {
+            writeln!(stdio.stderr, "read: {}", e.desc())?;
+            return Ok(1);
+        }
+    };
+
+    if names.is_empty() {
+        state.set_var("REPLY", &String::from_utf8_lossy(&line));
+    } else {
+        let ifs = state.var("IFS").unwrap_or(" \t\n").to_string();
+        let fields = split_fields(&line, &escaped, &ifs, names.len());
+        for (name, value) in names.iter().zip(fields) {
+            state.set_var(name, &value);
+        }
+    }
+    Ok(i32::from(!complete))
+}
+
+// stdinから区切り文字まで読む. 区切り文字より後ろを読みすぎないように, バッファリングせずに1バイトずつ読む
+// 読んだバイト列と, 各バイトがバックスラッシュでエスケープされていたか, 区切り文字まで読めたかを返す
+fn read_input(
+    stdin: BorrowedFd,
+    delimiter: u8,
+    raw: bool,
+) -> nix::Result<(Vec<u8>, Vec<bool>, bool)> {
+    let mut line = Vec::new();
+    let mut escaped = Vec::new();
+    let mut escape_next = false;
+    loop {
+        let mut byte = [0];
+        match unistd::read(stdin.as_raw_fd(), &mut byte) {
+            Ok(0) => return Ok((line, escaped, false)),
+            Ok(_) => (),
+            // Ctrl-Cで中断された場合だけ読むのをやめる
+            Err(Errno::EINTR) if !take_interrupted() => continue,
+            Err(e) => return Err(e),
+        }
+        let byte = byte[0];
+        if escape_next {
+            escape_next = false;
+            if byte != b'\n' {
+                line.push(byte);
+                escaped.push(true);
+            }
+        } else if byte == b'\\' && !raw {
+            escape_next = true;
+        } else if byte == delimiter {
+            return Ok((line, escaped, true));
+        } else {
+            line.push(byte);
+            escaped.push(false);
+        }
+    }
+}
+
+// readで読んだ行をifsの文字で区切ってcount個の値にする. 足りない分は空文字列で, 最後の値は区切らずに残り全て
+// ifsのうち空白文字は連続していても1つの区切りとして扱い, 行頭と行末のものは取り除く
+fn split_fields(line: &[u8], escaped: &[bool], ifs: &str, count: usize) -> Vec<String> {
+    let is_ifs = |i: usize| !escaped[i] && ifs.as_bytes().contains(&line[i]);
+    let is_space = |i: usize| is_ifs(i) && line[i].is_ascii_whitespace();
+    let skip_spaces = |mut i: usize| {
+        while i < line.len() && is_space(i) {
+            i += 1;
+        }
+        i
+    };
+
+    let mut fields = Vec::new();
+    let mut i = skip_spaces(0);
+    while fields.len() + 1 < count && i < line.len() {
+        let start = i;
+        while i < line.len() && !is_ifs(i) {
+            i += 1;
+        }
+        fields.push(String::from_utf8_lossy(&line[start..i]).into_owned());
+        // 区切りの空白と, 空白以外の区切り文字を1つだけ読み飛ばす
+        i = skip_spaces(i);
+        if i < line.len() && is_ifs(i) && !is_space(i) {
+            i = skip_spaces(i + 1);
+        }
+    }
+    let mut end = line.len();
+    while end > i && is_space(end - 1) {
+        end -= 1;
+    }
+    if fields.len() < count {
+        fields.push(String::from_utf8_lossy(&line[i..end]).into_owned());
+    }
+    fields.resize(count, String::new());
+    fields
+}
+
+// 値をシェルにそのまま貼り付けられる形で表示するためにクォートする
+fn quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./:,+@%=".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+// 履歴を番号付きで表示する. `history 10`なら直近の10件だけ, `history -c`で履歴を消す
+fn history(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let count = match args.first().map(|arg| arg.as_str()) {
+        None => state.history.len(),
+        Some("-c") => {
+            state.history.clear();
+            return Ok(0);
+        }
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                writeln!(stdio.stderr, "history: {}: numeric argument required", arg)?;
+                return Ok(2);
+            }
+        },
+    };
+
+    let start = state.history.len().saturating_sub(count);
+    for (i, entry) in state.history.iter().enumerate().skip(start) {
+        writeln!(stdio.stdout, "{:>5}  {}", i + 1, entry)?;
+    }
+    Ok(0)
+}
+
+// ジョブの一覧を表示する. -lでプロセスIDも, -pでプロセスグループIDだけを表示する
+fn jobs(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let long = args.iter().any(|arg| arg == "-l");
+    let pgid_only = args.iter().any(|arg| arg == "-p");
+
+    state.jobs.poll();
+    for id in state.jobs.ids() {
+        let Some(job) = state.jobs.get(id) else {
+            continue;
+        };
+        if pgid_only {
+            if let Some(pid) = job.pgid.or_else(|| job.pids().first().copied()) {
+                writeln!(stdio.stdout, "{}", pid)?;
+            }
+        } else if long {
+            let pids: Vec<String> = job.pids().iter().map(|pid| pid.to_string()).collect();
+            writeln!(
+                stdio.stdout,
+                "{} ({})",
+                state.jobs.format(job),
+                pids.join(" ")
+            )?;
+        } else {
+            writeln!(stdio.stdout, "{}", state.jobs.format(job))?;
+        }
+    }
+    // 終了したジョブは一度表示したら取り除く
+    state.jobs.take_finished();
+    Ok(0)
+}
+
+// %ジョブの指定をジョブ番号にする. 引数が無ければカレントジョブ
+fn resolve_job(state: &ShellState, args: &[String]) -> Result<usize, String> {
+    let spec = args.first().map_or("%+", |arg| arg.as_str());
+    state.jobs.resolve(spec).map_err(|e| {
+        if args.is_empty() {
+            "no current job".to_string()
+        } else {
+            e
+        }
+    })
+}
+
+// 停止中もしくはバックグラウンドのジョブをフォアグラウンドで再開して終了を待つ
+fn fg(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let id = match resolve_job(state, args) {
+        Ok(id) => id,
+        Err(e) => {
+            writeln!(stdio.stderr, "fg: {}", e)?;
+            return Ok(1);
+        }
+    };
+    let Some(mut job) = state.jobs.remove(id) else {
+        return Ok(1);
+    };
+
+    writeln!(stdio.stdout, "{}", job.command)?;
+    if let Err(e) = job.resume() {
+        writeln!(stdio.stderr, "fg: {}", e.desc())?;
+    }
+    Ok(wait_for_job(state, job))
+}
+
+// 停止中のジョブをバックグラウンドで再開する
+fn bg(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let id = match resolve_job(state, args) {
+        Ok(id) => id,
+        Err(e) => {
+            writeln!(stdio.stderr, "bg: {}", e)?;
+            return Ok(1);
+        }
+    };
+    let Some(job) = state.jobs.get_mut(id) else {
+        return Ok(1);
+    };
+
+    if let Err(e) = job.resume() {
+        writeln!(stdio.stderr, "bg: {}", e.desc())?;
+        return Ok(1);
+    }
+    writeln!(stdio.stdout, "[{}] {} &", id, job.command)?;
+    Ok(0)
+}
+
+// 指定したジョブ(もしくはプロセスID)が終了するまで待つ. 何も指定しなければ全てのバックグラウンドジョブを待つ
+fn wait(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let ids = if args.is_empty() {
+        state.jobs.ids()
+    } else {
+        let mut ids = Vec::new();
+        for arg in args {
+            let id = if arg.starts_with('%') {
+                state.jobs.resolve(arg)
+            } else {
+                find_job_by_pid(state, arg)
+            };
+            match id {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    writeln!(stdio.stderr, "wait: {}", e)?;
+                    return Ok(127);
+                }
+            }
+        }
+        ids
+    };
+
+    let mut status = 0;
+    for id in ids {
+        if let Some(job) = state.jobs.get_mut(id) {
+            job.wait();
+            let statuses = job.statuses();
+            status = pipeline_status(state, &statuses);
+        }
+    }
+    state.jobs.take_finished();
+    Ok(status)
+}
+
+fn find_job_by_pid(state: &ShellState, arg: &str) -> Result<usize, String> {
+    let not_child = || format!("pid {} is not a child of this shell", arg);
+    let pid = arg.parse::<i32>().map_err(|_| not_child())?;
+    state
+        .jobs
+        .ids()
+        .into_iter()
+        .find(|&id| {
+            state
+                .jobs
+                .get(id)
+                .is_some_and(|job| job.pids().contains(&Pid::from_raw(pid)))
+        })
+        .ok_or_else(not_child)
+}
+
+// kill [-s シグナル | -シグナル] %ジョブ|プロセスID ... でシグナルを送る. `kill -l`でシグナルの一覧を表示する
+fn kill_builtin(state: &mut ShellState, args: &[String], stdio: &mut Stdio) -> io::Result<i32> {
+    let mut signal = Signal::SIGTERM;
+    let mut targets = args;
+    match args.first().map(|arg| arg.as_str()) {
+        Some("-l") => {
+            for signal in Signal::iterator() {
+                writeln!(stdio.stdout, "{:>2}) {}", signal as i32, signal)?;
+            }
+            return Ok(0);
+        }
+        Some("-s") => match args.get(1).map(|name| parse_signal(name)) {
+            Some(Ok(parsed)) => {
+                signal = parsed;
+                targets = &args[2..];
+            }
+            Some(Err(e)) => {
+                writeln!(stdio.stderr, "kill: {}", e)?;
+                return Ok(1);
+            }
+            None => {
+                writeln!(stdio.stderr, "kill: -s: option requires an argument")?;
+                return Ok(2);
+            }
+        },
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => match parse_signal(&arg[1..]) {
+            Ok(parsed) => {
+                signal = parsed;
+                targets = &args[1..];
+            }
+            Err(e) => {
+                writeln!(stdio.stderr, "kill: {}", e)?;
+                return Ok(1);
+            }
+        },
+        _ => (),
+    }
+
+    if targets.is_empty() {
+        writeln!(
+            stdio.stderr,
+            "kill: usage: kill [-s sigspec | -sigspec] pid | jobspec ..."
+        )?;
+        return Ok(2);
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let result = if target.starts_with('%') {
+            state.jobs.resolve(target).and_then(|id| {
+                let job = state
+                    .jobs
+                    .get(id)
+                    .ok_or_else(|| format!("{}: no such job", target))?;
+                job.signal(signal)
+                    .map_err(|e| format!("{}: {}", target, e.desc()))?;
+                // 停止中のジョブは終了させるシグナルを受け取っても再開するまで死なないのでSIGCONTも送る
+                if job.is_stopped() && signal != Signal::SIGCONT {
+                    let _ = job.signal(Signal::SIGCONT);
+                }
+                Ok(())
+            })
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => kill(Pid::from_raw(pid), signal)
+                    .map_err(|e| format!("({}) - {}", pid, e.desc())),
+                Err(_) => Err(format!("{}: arguments must be process or job IDs", target)),
+            }
+        };
+        if let Err(e) = result {
+            writeln!(stdio.stderr, "kill: {}", e)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+// 9, KILL, SIGKILLのいずれの形式でも受け付ける
+fn parse_signal(name: &str) -> Result<Signal, String> {
+    let invalid = || format!("{}: invalid signal specification", name);
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number).map_err(|_| invalid());
+    }
+    let name = name.to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    Signal::from_str(&name).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsFd;
+
+    use super::*;
+
+    // inputを標準入力にしてビルトインを実行し, 終了ステータスと標準出力, 標準エラー出力を返す
+    fn call(state: &mut ShellState, input: &str, args: &[&str]) -> (i32, String, String) {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(input.as_bytes()).unwrap();
+        drop(writer);
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let mut stdio = Stdio {
+            stdin: reader.as_fd(),
+            stdout: &mut stdout,
+            stderr: &mut stderr,
+        };
+        let (name, args) = args.split_first().unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let status = run(state, name, &args, &mut stdio).unwrap();
+        (
+            status,
+            String::from_utf8(stdout).unwrap(),
+            String::from_utf8(stderr).unwrap(),
+        )
+    }
+
+    fn status(state: &mut ShellState, args: &[&str]) -> i32 {
+        call(state, "", args).0
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("9"), Ok(Signal::SIGKILL));
+        assert_eq!(parse_signal("KILL"), Ok(Signal::SIGKILL));
+        assert_eq!(parse_signal("sigterm"), Ok(Signal::SIGTERM));
+        assert_eq!(parse_signal("CONT"), Ok(Signal::SIGCONT));
+        assert!(parse_signal("NOPE").is_err());
+        assert!(parse_signal("999").is_err());
+    }
+
+    #[test]
+    fn test_export_unset() {
+        let mut state = ShellState::default();
+        state.set_var("LOCAL", "1");
+
+        assert_eq!(
+            status(&mut state, &["export", "LOCAL", "NEW=a b", "1X=2"]),
+            1
+        );
+        assert_eq!(
+            state.exported_vars(),
+            vec![
+                ("LOCAL".to_string(), "1".to_string()),
+                ("NEW".to_string(), "a b".to_string()),
+            ]
+        );
+
+        assert_eq!(status(&mut state, &["export", "-n", "LOCAL"]), 0);
+        assert_eq!(state.var("LOCAL"), Some("1"));
+        assert_eq!(state.exported_vars().len(), 1);
+
+        assert_eq!(status(&mut state, &["unset", "NEW", "LOCAL"]), 0);
+        assert!(state.vars.is_empty());
+    }
+
+    #[test]
+    fn test_set_positional() {
+        let mut state = ShellState {
+            positional: vec!["old".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(status(&mut state, &["set", "a", "-b", "c"]), 0);
+        assert_eq!(state.positional, vec!["a", "-b", "c"]);
+
+        assert_eq!(
+            status(&mut state, &["set", "-o", "pipefail", "--", "-x"]),
+            0
+        );
+        assert!(state.options.pipefail);
+        assert_eq!(state.positional, vec!["-x"]);
+
+        assert_eq!(status(&mut state, &["set", "--"]), 0);
+        assert!(state.positional.is_empty());
+
+        // 不正なオプションがあれば位置パラメータは変えない
+        state.positional = vec!["kept".to_string()];
+        assert_eq!(status(&mut state, &["set", "-x", "a"]), 2);
+        assert_eq!(state.positional, vec!["kept"]);
+    }
+
+    #[test]
+    fn test_local() {
+        let mut state = ShellState::default();
+        state.export_var("X", Some("global"));
+
+        assert_eq!(status(&mut state, &["local", "X=1"]), 1);
+        assert_eq!(state.var("X"), Some("global"));
+
+        state.push_local_scope();
+        assert_eq!(status(&mut state, &["local", "X=1", "Y"]), 0);
+        assert_eq!(state.var("X"), Some("1"));
+        assert_eq!(state.var("Y"), None);
+        // 2回目のlocalでは最初に覚えた値を上書きしない
+        assert_eq!(status(&mut state, &["local", "X=2"]), 0);
+        state.set_var("Y", "set");
+        state.pop_local_scope();
+
+        assert_eq!(state.var("X"), Some("global"));
+        assert_eq!(state.exported_vars().len(), 1);
+        assert_eq!(state.var("Y"), None);
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut state = ShellState::default();
+
+        assert_eq!(status(&mut state, &["alias", "ll=ls -l", "la=ls -a"]), 0);
+        assert_eq!(state.aliases.get("ll").map(String::as_str), Some("ls -l"));
+        assert_eq!(status(&mut state, &["alias", "ll", "nothing"]), 1);
+        assert_eq!(status(&mut state, &["alias", "a/b=x", "ok=1"]), 1);
+        assert_eq!(state.aliases.len(), 3);
+
+        assert_eq!(status(&mut state, &["unalias", "la", "la"]), 1);
+        assert_eq!(state.aliases.len(), 2);
+        assert_eq!(status(&mut state, &["unalias", "-a"]), 0);
+        assert!(state.aliases.is_empty());
+        assert_eq!(status(&mut state, &["unalias"]), 2);
+    }
+
+    #[test]
+    fn test_let() {
+        let mut state = ShellState::default();
+
+        assert_eq!(status(&mut state, &["let", "i = 2", "i *= 3"]), 0);
+        assert_eq!(state.var("i"), Some("6"));
+        assert_eq!(status(&mut state, &["let", "i -= 6"]), 1);
+        assert_eq!(status(&mut state, &["let", "i++"]), 1);
+        assert_eq!(state.var("i"), Some("1"));
+        assert_eq!(status(&mut state, &["let", "1 +", "i = 5"]), 1);
+        assert_eq!(state.var("i"), Some("1"));
+        assert_eq!(status(&mut state, &["let"]), 1);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut state = ShellState {
+            history: vec!["ls".to_string(), "echo hi".to_string()],
+            ..Default::default()
+        };
+        state.aliases.insert("ll".to_string(), "ls -l".to_string());
+
+        let output = |state: &mut ShellState, args: &[&str]| call(state, "", args).1;
+        assert_eq!(output(&mut state, &["echo", "-n", "a", "b"]), "a b");
+        assert_eq!(
+            output(&mut state, &["printf", "%s=%d\\n", "x", "1"]),
+            "x=1\n"
+        );
+        assert_eq!(output(&mut state, &["history", "1"]), "    2  echo hi\n");
+        assert_eq!(output(&mut state, &["alias"]), "alias ll='ls -l'\n");
+        assert_eq!(
+            output(&mut state, &["type", "-t", "ll", "cd", "if"]),
+            "alias\nbuiltin\nkeyword\n"
+        );
+        assert_eq!(
+            call(&mut state, "", &["shift", "x"]),
+            (
+                2,
+                String::new(),
+                "shift: x: numeric argument required\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        let mut state = ShellState::default();
+
+        assert_eq!(call(&mut state, "a b c\nnext\n", &["read", "x", "y"]).0, 0);
+        assert_eq!(state.var("x"), Some("a"));
+        assert_eq!(state.var("y"), Some("b c"));
+        assert_eq!(call(&mut state, "one\\\ntwo", &["read"]).0, 1);
+        assert_eq!(state.var("REPLY"), Some("onetwo"));
+        assert_eq!(call(&mut state, "a\\b", &["read", "-r"]).0, 1);
+        assert_eq!(state.var("REPLY"), Some("a\\b"));
+    }
+
+    #[test]
+    fn test_split_fields() {
+        let split = |line: &str, ifs: &str, count: usize| {
+            // \の次の文字はエスケープされたものとして扱う
+            let mut bytes = Vec::new();
+            let mut escaped = Vec::new();
+            let mut chars = line.bytes();
+            while let Some(b) = chars.next() {
+                match b {
+                    b'\\' => {
+                        bytes.extend(chars.next());
+                        escaped.push(true);
+                    }
+                    b => {
+                        bytes.push(b);
+                        escaped.push(false);
+                    }
+                }
+            }
+            split_fields(&bytes, &escaped, ifs, count)
+        };
+        assert_eq!(split("  a  b  c  ", " \t\n", 2), vec!["a", "b  c"]);
+        assert_eq!(split("a b", " \t\n", 3), vec!["a", "b", ""]);
+        assert_eq!(split("a:b::c", ":", 4), vec!["a", "b", "", "c"]);
+        assert_eq!(split(" a : b ", " :", 2), vec!["a", "b"]);
+        assert_eq!(split("a\\ b c", " ", 2), vec!["a b", "c"]);
+        assert_eq!(split("", " ", 2), vec!["", ""]);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let normalize = |path: &str| normalize_path(Path::new(path)).display().to_string();
+        assert_eq!(normalize("/a/b/../c/./d/"), "/a/c/d");
+        assert_eq!(normalize("/a/.."), "/");
+        assert_eq!(normalize("/.."), "/");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/usr/bin:/bin"), "/usr/bin:/bin");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("it's here"), r"'it'\''s here'");
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+use crate::{builtins, state::ShellState};
+
+// 補完候補に含める時にバックスラッシュでエスケープする文字
+const SPECIAL_CHARS: &str = " \t\\'\"|&;<>()$`*?[]#!{}";
+
+// Tabで単語を補完する. コマンド名の位置ではビルトインと$PATHにある実行ファイルを,
+// それ以外ではファイル名を, `$`の後ろでは変数名を補完する
+// 補完中にShellStateを借りることはできないので, 必要な情報はプロンプトを出す前にupdateで写しておく
+#[derive(Debug, Default)]
+pub struct ShellHelper {
+    path: Option<String>,
+    home: Option<String>,
+    vars: Vec<String>,
+    functions: Vec<String>,
+}
+
+impl ShellHelper {
+    pub fn update(&mut self, state: &ShellState) {
+        self.path = state.var("PATH").map(str::to_string);
+        self.home = state.var("HOME").map(str::to_string);
+        self.vars = state.vars.keys().cloned().collect();
+        self.functions = state.functions.keys().cloned().collect();
+    }
+
+    // lineのposまでを見て, 補完する単語の開始位置と候補を返す
+    fn complete_line(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+
+        let (start, mut candidates) = if let Some(dollar) = word.rfind('$') {
+            let name = &word[dollar + 1..];
+            let (name, braced) = match name.strip_prefix('{') {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            if name.chars().all(|c| c == '_' || c.is_ascii_alphanumeric()) {
+                (start + dollar, self.complete_var(name, braced))
+            } else {
+                (start, Vec::new())
+            }
+        } else if is_command_position(&line[..start]) && !word.contains('/') {
+            (start, self.complete_command(&unescape(word)))
+        } else {
+            (start, self.complete_path(&unescape(word)))
+        };
+
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates.dedup_by(|a, b| a.display == b.display);
+        (start, candidates)
+    }
+
+    // `${HO`のように波括弧で始めた場合は, 置き換えた後も`${HOME}`と波括弧で囲む
+    fn complete_var(&self, prefix: &str, braced: bool) -> Vec<Pair> {
+        self.vars
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: if braced {
+                    format!("${{{}}}", name)
+                } else {
+                    format!("${}", name)
+                },
+            })
+            .collect()
+    }
+
+    fn complete_command(&self, prefix: &str) -> Vec<Pair> {
+        let builtins = builtins::names().map(|name| name.to_string());
+        let functions = self.functions.iter().cloned();
+        let executables = self
+            .path
+            .iter()
+            .flat_map(|path| path.split(':'))
+            .filter(|dir| !dir.is_empty())
+            .flat_map(|dir| executables_in(Path::new(dir)));
+
+        builtins
+            .chain(functions)
+            .chain(executables)
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                replacement: format!("{} ", escape(&name)),
+                display: name,
+            })
+            .collect()
+    }
+
+    // `src/ma`のような入力は/の前をディレクトリとして扱い, その中からmaで始まるものを探す
+    // ディレクトリの場合は続けて中身を補完できるように/を付け, ファイルの場合は空白を付ける
+    fn complete_path(&self, word: &str) -> Vec<Pair> {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word),
+        };
+        let dir_path = match (dir.strip_prefix('~'), &self.home) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                PathBuf::from(format!("{}{}", home, rest))
+            }
+            _ if dir.is_empty() => PathBuf::from("."),
+            _ => PathBuf::from(dir),
+        };
+
+        let Ok(entries) = fs::read_dir(&dir_path) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // .で始まるファイルは.を入力した場合だけ候補にする
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+                let suffix = if is_dir { "/" } else { " " };
+                Some(Pair {
+                    display: if is_dir {
+                        format!("{}/", name)
+                    } else {
+                        name.clone()
+                    },
+                    replacement: format!("{}{}{}", escape(dir), escape(&name), suffix),
+                })
+            })
+            .collect()
+    }
+}
+
+// 補完する単語の開始位置. 空白や`|`, `;`などの区切りの直後から始まる
+fn word_start(line: &str, pos: usize) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line[..pos].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c.is_whitespace() || "|&;<>()".contains(c) {
+            start = i + c.len_utf8();
+        }
+    }
+    start
+}
+
+// 単語の前に何も無いか, `|`や`;`, `&&`などの直後であればコマンド名を入力している
+fn is_command_position(before: &str) -> bool {
+    match before.trim_end().chars().last() {
+        None => true,
+        Some(c) => "|&;(".contains(c),
+    }
+}
+
+fn executables_in(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            fs::metadata(entry.path())
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if SPECIAL_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(self.complete_line(line, pos))
+    }
+}
+
+// 補完以外の機能は使わないので, デフォルトの実装のままにしておく
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn replacements(helper: &ShellHelper, line: &str) -> (usize, Vec<String>) {
+        let (start, pairs) = helper.complete_line(line, line.len());
+        (
+            start,
+            pairs.into_iter().map(|pair| pair.replacement).collect(),
+        )
+    }
+
+    #[test]
+    fn test_word_start() {
+        assert_eq!(word_start("ls -l sr", 8), 6);
+        assert_eq!(word_start("cat a|gr", 8), 6);
+        assert_eq!(word_start("ls my\\ fi", 9), 3);
+        assert_eq!(word_start("", 0), 0);
+    }
+
+    #[test]
+    fn test_is_command_position() {
+        assert!(is_command_position(""));
+        assert!(is_command_position("ls | "));
+        assert!(is_command_position("make && "));
+        assert!(!is_command_position("ls "));
+    }
+
+    #[test]
+    fn test_complete() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::create_dir_all(dir.join("src dir")).unwrap();
+        fs::write(dir.join("main.rs"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let command = dir.join("bin/mytool");
+        fs::write(&command, "").unwrap();
+        fs::set_permissions(&command, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("bin/mydata"), "").unwrap();
+
+        let dir = dir.to_str().unwrap().to_string();
+        let helper = ShellHelper {
+            path: Some(format!("{}/bin", dir)),
+            home: Some(dir.clone()),
+            vars: vec![
+                "HOME".to_string(),
+                "HISTFILE".to_string(),
+                "PATH".to_string(),
+            ],
+            functions: vec!["myfunc".to_string()],
+        };
+
+        assert_eq!(
+            replacements(&helper, "myt"),
+            (0, vec!["mytool ".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "myf"),
+            (0, vec!["myfunc ".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "ls | ex"),
+            (
+                5,
+                vec![
+                    "exec ".to_string(),
+                    "exit ".to_string(),
+                    "export ".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            replacements(&helper, "echo $H"),
+            (5, vec!["$HISTFILE".to_string(), "$HOME".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "echo ${HO"),
+            (5, vec!["${HOME}".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "echo a${P"),
+            (6, vec!["${PATH}".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "cat ~/"),
+            (
+                4,
+                vec![
+                    "~/bin/".to_string(),
+                    "~/main.rs ".to_string(),
+                    "~/src\\ dir/".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            replacements(&helper, "cat ~/.h"),
+            (4, vec!["~/.hidden ".to_string()])
+        );
+        assert_eq!(
+            replacements(&helper, "cd ~/src\\ "),
+            (3, vec!["~/src\\ dir/".to_string()])
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs::{self, Metadata},
+    io::IsTerminal,
+    os::{
+        fd::BorrowedFd,
+        unix::fs::{FileTypeExt, MetadataExt},
+    },
+};
+
+use nix::unistd::{access, AccessFlags};
+
+use crate::state::ShellState;
+
+const UNARY_OPERATORS: [&str; 20] = [
+    "-e", "-f", "-d", "-r", "-w", "-x", "-s", "-L", "-h", "-b", "-c", "-p", "-S", "-g", "-u", "-k",
+    "-t", "-z", "-n", "-v",
+];
+const BINARY_OPERATORS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+// test, [の条件式を評価する. 式が間違っている場合はエラーメッセージを返す
+//   ! expr  ( expr )  expr -a expr  expr -o expr  -aは-oより優先する
+//   -e file などのファイルの判定, -z str -n str, str = str などの文字列の比較, n -eq n などの整数の比較
+// 引数が1つだけの場合はそれが空文字列でないかどうか. `test -f`のような場合も演算子ではなく文字列として扱う
+pub fn evaluate(state: &ShellState, args: &[String]) -> Result<bool, String> {
+    let mut parser = Parser {
+        state,
+        args,
+        pos: 0,
+    };
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let result = parser.or()?;
+    match args.get(parser.pos) {
+        Some(arg) => Err(format!("{}: unexpected argument", arg)),
+        None => Ok(result),
+    }
+}
+
+struct Parser<'a> {
+    state: &'a ShellState,
+    args: &'a [String],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn remaining(&self) -> usize {
+        self.args.len() - self.pos
+    }
+
+    fn peek(&self, offset: usize) -> Option<&str> {
+        self.args.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let arg = self
+            .args
+            .get(self.pos)
+            .ok_or_else(|| "argument expected".to_string())?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            // 左辺の結果に関わらず右辺も読み進める必要があるので, 評価してから組み合わせる
+            let right = self.and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            let right = self.not()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // `test !`は"!"という空でない文字列
+        if self.peek(0) == Some("!") && self.remaining() > 1 {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        // `[ = = = ]`のように2番目が二項演算子なら, 先頭が何であっても比較として扱う
+        if self.remaining() >= 3 {
+            if let Some(op) = self.peek(1).filter(|op| BINARY_OPERATORS.contains(op)) {
+                let op = op.to_string();
+                let left = self.next()?.to_string();
+                self.pos += 1;
+                let right = self.next()?.to_string();
+                return binary(&left, &op, &right);
+            }
+        }
+        if self.peek(0) == Some("(") && self.remaining() >= 3 {
+            self.pos += 1;
+            let result = self.or()?;
+            if self.next()? != ")" {
+                return Err("`)' expected".to_string());
+            }
+            return Ok(result);
+        }
+        if self.remaining() >= 2 {
+            if let Some(op) = self.peek(0).filter(|op| UNARY_OPERATORS.contains(op)) {
+                let op = op.to_string();
+                self.pos += 1;
+                let operand = self.next()?.to_string();
+                return unary(self.state, &op, &operand);
+            }
+        }
+        Ok(!self.next()?.is_empty())
+    }
+}
+
+fn unary(state: &ShellState, op: &str, operand: &str) -> Result<bool, String> {
+    let metadata = || fs::metadata(operand).ok();
+    let has = |f: fn(&Metadata) -> bool| metadata().is_some_and(|m| f(&m));
+    let result = match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-v" => state.var(operand).is_some(),
+        "-e" => metadata().is_some(),
+        "-f" => has(|m| m.is_file()),
+        "-d" => has(|m| m.is_dir()),
+        "-s" => has(|m| m.len() > 0),
+        "-b" => has(|m| m.file_type().is_block_device()),
+        "-c" => has(|m| m.file_type().is_char_device()),
+        "-p" => has(|m| m.file_type().is_fifo()),
+        "-S" => has(|m| m.file_type().is_socket()),
+        "-u" => has(|m| m.mode() & 0o4000 != 0),
+        "-g" => has(|m| m.mode() & 0o2000 != 0),
+        "-k" => has(|m| m.mode() & 0o1000 != 0),
+        "-L" | "-h" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => access(operand, AccessFlags::R_OK).is_ok(),
+        "-w" => access(operand, AccessFlags::W_OK).is_ok(),
+        "-x" => access(operand, AccessFlags::X_OK).is_ok(),
+        "-t" => {
+            let fd = parse_integer(operand)?;
+            // 開いていないfdを渡すとis_terminalはfalseを返すだけなので問題ない
+            i32::try_from(fd)
+                .is_ok_and(|fd| fd >= 0 && unsafe { BorrowedFd::borrow_raw(fd) }.is_terminal())
+        }
+        _ => return Err(format!("{}: unary operator expected", op)),
+    };
+    Ok(result)
+}
+
+fn binary(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    let result = match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+            let (left, right) = (parse_integer(left)?, parse_integer(right)?);
+            match op {
+                "-eq" => left == right,
+                "-ne" => left != right,
+                "-lt" => left < right,
+                "-le" => left <= right,
+                "-gt" => left > right,
+                _ => left >= right,
+            }
+        }
+        // 存在しないファイルは存在するどのファイルよりも古いものとして扱う
+        "-nt" | "-ot" => {
+            let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+            let (left, right) = (modified(left), modified(right));
+            if op == "-nt" {
+                left > right
+            } else {
+                right > left
+            }
+        }
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => return Err(format!("{}: binary operator expected", op)),
+    };
+    Ok(result)
+}
+
+fn parse_integer(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("{}: integer expression expected", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn test(args: &str) -> Result<bool, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        evaluate(&ShellState::default(), &args)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(test(""), Ok(false));
+        assert_eq!(test("x"), Ok(true));
+        assert_eq!(test("-f"), Ok(true));
+        assert_eq!(test("!"), Ok(true));
+        assert_eq!(test("! x"), Ok(false));
+        assert_eq!(test("-n x"), Ok(true));
+        assert_eq!(test("-z x"), Ok(false));
+        assert_eq!(test("a = a"), Ok(true));
+        assert_eq!(test("= = ="), Ok(true));
+        assert_eq!(test("a != a"), Ok(false));
+        assert_eq!(test("abc < abd"), Ok(true));
+        assert_eq!(test("10 -gt 9"), Ok(true));
+        assert_eq!(test("-3 -le -3"), Ok(true));
+        assert_eq!(
+            test("a -eq 1"),
+            Err("a: integer expression expected".to_string())
+        );
+        assert_eq!(test("1 -eq 2 -o 2 -eq 2"), Ok(true));
+        assert_eq!(test("1 -eq 1 -a ! 2 -eq 2"), Ok(false));
+        // -aの方が-oより優先する
+        assert_eq!(test("x -o -z x -a -z x"), Ok(true));
+        assert_eq!(test("( 1 -eq 2 -o 2 -eq 2 ) -a x"), Ok(true));
+        assert!(test("( x").is_err());
+        assert!(test("a b").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_files() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let file = dir.join("file");
+        fs::write(&file, "content").unwrap();
+        let empty = dir.join("empty");
+        fs::write(&empty, "").unwrap();
+        let (dir_path, file, empty) = (
+            dir.display().to_string(),
+            file.display().to_string(),
+            empty.display().to_string(),
+        );
+
+        assert_eq!(test(&format!("-d {}", dir_path)), Ok(true));
+        assert_eq!(test(&format!("-f {}", dir_path)), Ok(false));
+        assert_eq!(test(&format!("-f {} -a -s {}", file, file)), Ok(true));
+        assert_eq!(test(&format!("-s {}", empty)), Ok(false));
+        assert_eq!(test(&format!("-e {}/missing", dir_path)), Ok(false));
+        assert_eq!(test(&format!("{} -ef {}", file, file)), Ok(true));
+        assert_eq!(
+            test(&format!("{} -nt {}/missing", file, dir_path)),
+            Ok(true)
+        );
+        assert_eq!(test(&format!("-r {}", file)), Ok(true));
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fmt::Display, io};
+
+use nix::errno::Errno;
+
+use crate::parser::ParseError;
+
+// シェル自体のエラー. 表示する時は`shell: `を前に付ける
+#[derive(Debug)]
+pub enum ShellError {
+    Syntax(ParseError),
+    // コマンドを起動できなかった
+    Spawn { command: String, error: io::Error },
+    // カレントディレクトリが削除されているなどで取得できない
+    CurrentDir(io::Error),
+    // 入力の終わりに達した(対話モードではCtrl-D). エラーではなく終了の合図として使う
+    Eof,
+    // 入力の読み込みや標準入出力の差し替えに失敗した
+    Io(io::Error),
+}
+
+impl ShellError {
+    // このエラーで終わったコマンドやシェル自体の終了ステータス
+    // 構文エラーは2, コマンドが見つからない場合は127, それ以外で起動できなかった場合は126. POSIXのshと同じ
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::Syntax(_) => 2,
+            ShellError::Spawn { error, .. } if error.kind() == io::ErrorKind::NotFound => 127,
+            ShellError::Spawn { .. } => 126,
+            ShellError::CurrentDir(_) | ShellError::Io(_) => 1,
+            ShellError::Eof => 0,
+        }
+    }
+}
+
+impl From<ParseError> for ShellError {
+    fn from(e: ParseError) -> Self {
+        ShellError::Syntax(e)
+    }
+}
+
+impl From<io::Error> for ShellError {
+    fn from(e: io::Error) -> Self {
+        ShellError::Io(e)
+    }
+}
+
+impl Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::Syntax(e) => write!(f, "{}", e),
+            ShellError::Spawn { command, error } if error.kind() == io::ErrorKind::NotFound => {
+                write!(f, "{}: command not found", command)
+            }
+            ShellError::Spawn { command, error } => {
+                write!(f, "{}: {}", command, describe(error))
+            }
+            ShellError::CurrentDir(e) => {
+                write!(f, "error retrieving current directory: {}", describe(e))
+            }
+            ShellError::Eof => write!(f, "unexpected end of input"),
+            ShellError::Io(e) => write!(f, "{}", describe(e)),
+        }
+    }
+}
+
+// io::ErrorをそのままDisplayすると`No such file or directory (os error 2)`のように番号が付くので,
+// OSのエラーはstrerrorと同じ説明だけにする. 独自のメッセージを持つエラーはそのまま
+pub fn describe(e: &io::Error) -> String {
+    match e.raw_os_error() {
+        Some(errno) => Errno::from_raw(errno).desc().to_string(),
+        None => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_error() {
+        let spawn = |kind: io::ErrorKind| ShellError::Spawn {
+            command: "foo".to_string(),
+            error: kind.into(),
+        };
+        let not_found = spawn(io::ErrorKind::NotFound);
+        assert_eq!(not_found.to_string(), "foo: command not found");
+        assert_eq!(not_found.status(), 127);
+        assert_eq!(spawn(io::ErrorKind::PermissionDenied).status(), 126);
+
+        let syntax = ShellError::from(ParseError::UnexpectedEof);
+        assert_eq!(syntax.to_string(), "syntax error: unexpected end of file");
+        assert_eq!(syntax.status(), 2);
+
+        let cwd = ShellError::CurrentDir(io::ErrorKind::NotFound.into());
+        assert!(cwd
+            .to_string()
+            .starts_with("error retrieving current directory: "));
+        assert_eq!(cwd.status(), 1);
+    }
+
+    #[test]
+    fn test_describe() {
+        let not_found = io::Error::from_raw_os_error(Errno::ENOENT as i32);
+        assert_eq!(describe(&not_found), "No such file or directory");
+        assert_eq!(
+            ShellError::Io(not_found).to_string(),
+            "No such file or directory"
+        );
+        let custom = io::Error::new(io::ErrorKind::InvalidInput, "foo: ambiguous redirect");
+        assert_eq!(describe(&custom), "foo: ambiguous redirect");
+    }
+}
@@ -0,0 +1,824 @@
+use std::{
+    fs::File,
+    io::{self, pipe, PipeReader, Read, Write},
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{self, Child, Command},
+};
+
+use nix::{
+    sys::signal::Signal,
+    unistd::{close, fork, getpgrp, setpgid, tcsetpgrp, ForkResult, Pid},
+};
+
+use crate::{
+    ast::{
+        AndOr, Assignment, CaseItem, Command as AstCommand, CompoundCommand, Connector,
+        FunctionDef, List, Pipeline, Redirect, SimpleCommand,
+    },
+    builtins,
+    error::{describe, ShellError},
+    expand::{expand_assignment, expand_word, expand_words, matches_pattern, ExpandError},
+    job::{Job, JobTable, Process},
+    parser::parse_with_aliases,
+    redirect::{apply_redirects, redirect_shell, Streams},
+    signal::{restore_default_signals, restore_sigpipe},
+    state::{Flow, ShellState},
+    token::Word,
+};
+
+// シェルの状態と, コマンドを実行する時に使う標準入出力をまとめたもの. ライブラリとして使う場合の入り口
+// 標準入出力を指定した場合は, 実行している間だけシェル自身の0, 1, 2番のfdを差し替える
+// 外部コマンドだけでなくビルトインの出力も差し替えた先に書かれる
+// 差し替えはdup2でプロセス全体のfdに対して行うので, 複数のExecutorを別々のスレッドで同時に実行してはいけない
+// 実行中は同じプロセスの他のスレッドの標準入出力も差し替えた先に向く
+#[derive(Debug, Default)]
+pub struct Executor {
+    pub state: ShellState,
+    stdin: Option<OwnedFd>,
+    stdout: Option<OwnedFd>,
+    stderr: Option<OwnedFd>,
+}
+
+impl Executor {
+    pub fn new(state: ShellState) -> Self {
+        Executor {
+            state,
+            ..Default::default()
+        }
+    }
+
+    pub fn stdin(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stdin = Some(fd.into());
+        self
+    }
+
+    pub fn stdout(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stdout = Some(fd.into());
+        self
+    }
+
+    pub fn stderr(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stderr = Some(fd.into());
+        self
+    }
+
+    // sourceをパースして実行し, 終了ステータスを返す. 空の入力の場合は何もせずに$?をそのまま返す
+    pub fn run(&mut self, source: &str) -> Result<i32, ShellError> {
+        match parse_with_aliases(source, &self.state.aliases)? {
+            Some(list) => self.execute(&list),
+            None => Ok(self.state.last_status),
+        }
+    }
+
+    // exitが実行された場合はその終了ステータスを返す. 続けて実行するかどうかはstate.exit_statusで判断する
+    pub fn execute(&mut self, list: &List) -> Result<i32, ShellError> {
+        let duplicate = |fd: &Option<OwnedFd>| fd.as_ref().map(OwnedFd::try_clone).transpose();
+        let streams = Streams {
+            stdin: duplicate(&self.stdin)?,
+            stdout: duplicate(&self.stdout)?,
+            stderr: duplicate(&self.stderr)?,
+            ..Default::default()
+        };
+        let saved = redirect_shell(streams)?;
+        execute_list(&mut self.state, list);
+        saved.restore();
+        Ok(self.state.exit_status.unwrap_or(self.state.last_status))
+    }
+}
+
+pub fn execute_list(state: &mut ShellState, list: &List) {
+    for and_or in &list.and_ors {
+        if and_or.background {
+            execute_background(state, and_or);
+        } else {
+            execute_and_or(state, and_or);
+        }
+        if state.is_unwinding() {
+            return;
+        }
+    }
+}
+
+fn execute_and_or(state: &mut ShellState, and_or: &AndOr) {
+    let mut status = execute_pipeline(state, &and_or.first);
+    for (connector, pipeline) in &and_or.rest {
+        if state.is_unwinding() {
+            return;
+        }
+        // `a && b || c`は左から順に評価し, 直前に実行したパイプラインの結果で次を実行するかを決める
+        let should_run = match connector {
+            Connector::And => status == 0,
+            Connector::Or => status != 0,
+        };
+        if should_run {
+            status = execute_pipeline(state, pipeline);
+        }
+    }
+}
+
+// `cmd &`のように末尾に&が付いたコマンドは終了を待たずにジョブテーブルに登録する
+fn execute_background(state: &mut ShellState, and_or: &AndOr) {
+    let job = if and_or.rest.is_empty() {
+        spawn_pipeline(state, &and_or.first, false)
+    } else {
+        // `make && ./a.out &`のように&&や||を含む場合は, 全体をサブシェルで実行する
+        let command = and_or.to_string();
+        let result = fork_subshell(state, None, false, |state| {
+            execute_and_or(state, and_or);
+            state.exit_status.unwrap_or(state.last_status)
+        });
+        match result {
+            Ok(pid) => {
+                let pgid = state.terminal.is_some().then_some(pid);
+                Job::new(&command, pgid, vec![Process::spawned(pid, &command)])
+            }
+            Err(e) => {
+                eprintln!("shell: fork: {}", e.desc());
+                state.last_status = 1;
+                return;
+            }
+        }
+    };
+
+    let last_pid = job.pids().last().copied();
+    if last_pid.is_some() {
+        state.last_background_pid = last_pid;
+    }
+    let id = state.jobs.add(job);
+    if let (Some(_), Some(pid)) = (&state.terminal, last_pid) {
+        eprintln!("[{}] {}", id, pid);
+    }
+    state.last_status = 0;
+}
+
+// パイプラインを実行して終了ステータスを返す. 結果は$?で参照できるようにstateにも記録する
+pub fn execute_pipeline(state: &mut ShellState, pipeline: &Pipeline) -> i32 {
+    let status = match pipeline.commands.as_slice() {
+        // 複合コマンドだけのパイプラインはforkせずにシェル自身の中で実行する
+        // ループの中で代入した変数を後から参照できるようにするため
+        [AstCommand::Compound(command, redirects)] => {
+            execute_compound(state, command, redirects);
+            state.pipestatus = vec![state.last_status];
+            state.last_status
+        }
+        _ => {
+            let job = spawn_pipeline(state, pipeline, true);
+            wait_for_job(state, job)
+        }
+    };
+    if pipeline.negated {
+        state.last_status = i32::from(status == 0);
+    }
+    state.last_status
+}
+
+// 複合コマンドのリダイレクトはシェル自身のfdを一時的に差し替えて適用し, 終わったら元に戻す
+fn execute_compound(state: &mut ShellState, command: &CompoundCommand, redirects: &[Redirect]) {
+    let mut streams = Streams::default();
+    let saved =
+        apply_redirects(state, redirects, &mut streams).and_then(|()| redirect_shell(streams));
+    let saved = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            eprintln!("shell: {}", describe(&e));
+            state.last_status = 1;
+            return;
+        }
+    };
+
+    match command {
+        CompoundCommand::If {
+            conditions,
+            else_body,
+        } => execute_if(state, conditions, else_body.as_ref()),
+        CompoundCommand::While {
+            condition,
+            body,
+            until,
+        } => execute_while(state, condition, body, *until),
+        CompoundCommand::For { name, words, body } => {
+            execute_for(state, name, words.as_deref(), body)
+        }
+        CompoundCommand::Case { word, items } => execute_case(state, word, items),
+        CompoundCommand::Group(list) => execute_list(state, list),
+        CompoundCommand::Subshell(list) => execute_subshell(state, command, list),
+    }
+    saved.restore();
+}
+
+// ( list ). forkした子プロセスの中で実行するので, 変数やカレントディレクトリの変更, exitは呼び出し元に残らない
+// フォアグラウンドのジョブとして終了を待つので, Ctrl-CやCtrl-Zはサブシェルの方に届く
+fn execute_subshell(state: &mut ShellState, command: &CompoundCommand, list: &List) {
+    let name = command.to_string();
+    let result = fork_subshell(state, None, true, |state| {
+        execute_list(state, list);
+        state.exit_status.unwrap_or(state.last_status)
+    });
+    match result {
+        Ok(pid) => {
+            let pgid = state.terminal.is_some().then_some(pid);
+            let job = Job::new(&name, pgid, vec![Process::spawned(pid, &name)]);
+            wait_for_job(state, job);
+        }
+        Err(e) => {
+            eprintln!("shell: fork: {}", e.desc());
+            state.last_status = 1;
+        }
+    }
+}
+
+// どの条件も成立せずelseも無い場合の終了ステータスは0
+fn execute_if(state: &mut ShellState, conditions: &[(List, List)], else_body: Option<&List>) {
+    for (condition, body) in conditions {
+        execute_list(state, condition);
+        if state.is_unwinding() {
+            return;
+        }
+        if state.last_status == 0 {
+            execute_list(state, body);
+            return;
+        }
+    }
+    match else_body {
+        Some(body) => execute_list(state, body),
+        None => state.last_status = 0,
+    }
+}
+
+// ループの終了ステータスは最後に実行した本体の終了ステータス. 一度も実行しなければ0
+fn execute_while(state: &mut ShellState, condition: &List, body: &List, until: bool) {
+    let mut status = 0;
+    state.loop_depth += 1;
+    loop {
+        execute_list(state, condition);
+        if state.is_unwinding() {
+            if next_iteration(state) {
+                continue;
+            }
+            break;
+        }
+        if (state.last_status == 0) == until {
+            break;
+        }
+        execute_list(state, body);
+        status = state.last_status;
+        if !next_iteration(state) {
+            break;
+        }
+    }
+    state.loop_depth -= 1;
+    if !state.is_unwinding() {
+        state.last_status = status;
+    }
+}
+
+// `for x`のようにinが省略された場合は位置パラメータを順に代入する
+fn execute_for(state: &mut ShellState, name: &str, words: Option<&[Word]>, body: &List) {
+    let items = match words {
+        Some(words) => match expand_words(state, words) {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                state.last_status = 1;
+                return;
+            }
+        },
+        None => state.positional.clone(),
+    };
+
+    let mut status = 0;
+    state.loop_depth += 1;
+    for item in items {
+        state.set_var(name, &item);
+        execute_list(state, body);
+        status = state.last_status;
+        if !next_iteration(state) {
+            break;
+        }
+    }
+    state.loop_depth -= 1;
+    if !state.is_unwinding() {
+        state.last_status = status;
+    }
+}
+
+// break/continueを1段分消費して, このループを続けるかどうかを返す
+// `break 2`や`continue 2`の場合は残りの段数を外側のループに引き継ぐ
+// returnの場合は関数の呼び出し元まで戻るので, 消費せずにループを抜ける
+fn next_iteration(state: &mut ShellState) -> bool {
+    match state.flow {
+        Some(Flow::Break(count)) => {
+            state.flow = (count > 1).then(|| Flow::Break(count - 1));
+            false
+        }
+        Some(Flow::Continue(count)) => {
+            state.flow = (count > 1).then(|| Flow::Continue(count - 1));
+            count == 1
+        }
+        Some(Flow::Return) => false,
+        None => state.exit_status.is_none(),
+    }
+}
+
+// 上から順にパターンを試し, 最初にマッチしたものの本体だけを実行する
+fn execute_case(state: &mut ShellState, word: &Word, items: &[CaseItem]) {
+    let value = match expand_word(state, word) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("shell: {}", e);
+            state.last_status = 1;
+            return;
+        }
+    };
+    for item in items {
+        for pattern in &item.patterns {
+            match matches_pattern(state, pattern, &value) {
+                Ok(true) => {
+                    state.last_status = 0;
+                    execute_list(state, &item.body);
+                    return;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("shell: {}", e);
+                    state.last_status = 1;
+                    return;
+                }
+            }
+        }
+    }
+    state.last_status = 0;
+}
+
+// $(...)のコマンドをサブシェルで実行して, 標準出力に書かれた内容を返す. 終了ステータスは$?に反映する
+pub fn capture_output(state: &mut ShellState, list: &List) -> io::Result<String> {
+    let (mut reader, writer) = pipe()?;
+    let streams = Streams {
+        stdout: Some(writer.into()),
+        ..Default::default()
+    };
+    // シェル自身と同じプロセスグループに入れておくと, Ctrl-Cを押した時にシェルは無視して子プロセスだけが止まる
+    let pgid = state.terminal.is_some().then(getpgrp);
+    let pid = fork_subshell(state, pgid, false, |state| {
+        if let Err(e) = redirect_shell(streams) {
+            eprintln!("shell: {}", describe(&e));
+            return 1;
+        }
+        execute_list(state, list);
+        state.exit_status.unwrap_or(state.last_status)
+    })?;
+
+    // 書き込み側はforkした時点で子プロセスにしか残っていないので, 子プロセスが終了すればEOFになる
+    let mut output = Vec::new();
+    let result = reader.read_to_end(&mut output);
+    let mut job = Job::new(&list.to_string(), None, vec![Process::spawned(pid, "")]);
+    job.wait();
+    state.last_status = job.statuses().last().copied().unwrap_or(0);
+    result?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+// パイプラインの各コマンドを起動してジョブとして返す. 終了は待たない
+fn spawn_pipeline(state: &mut ShellState, pipeline: &Pipeline, foreground: bool) -> Job {
+    // peekableは"consume"しないで次の値を覗き見することができるiterator. 名前のまんま
+    let mut commands = pipeline.commands.iter().peekable();
+    let mut processes: Vec<Process> = Vec::new();
+    let mut previous_stdout: Option<PipeReader> = None;
+    // ジョブ制御が有効な場合, パイプラインの最初のコマンドのプロセスIDをプロセスグループIDにする
+    let mut pgid: Option<Pid> = None;
+    // フォアグラウンドで1つだけのコマンドであれば, 関数をシェル自身の中で実行して変数の変更などを残せる
+    let in_process = foreground && pipeline.commands.len() == 1;
+
+    // "|"で区切られたコマンドをiterateして処理する
+    while let Some(command) = commands.next() {
+        // 例えばコマンドが`cat file.txt | grep something`の時を例にして考えてみる
+        // 1. previous_stdoutがNone, つまりcommandがcatの場合
+        //     親プロセスの標準入力を受け取る -> terminalに表示されている画面のカーソルから打ち込まれた文字列が標準入力になる.
+        // 2. previous_stdoutがSome, つまりcommandがgrepの場合
+        //     前回のコマンド`cat file.txt`の標準出力に繋がったpipeを標準入力にする. その結果grep somethingに対してfile.txtの内容が入力される.
+        let mut streams = Streams {
+            stdin: previous_stdout.take().map(Into::into),
+            ..Default::default()
+        };
+
+        // ジョブ制御が無い場合, バックグラウンドのコマンドが端末の入力を奪い合わないように標準入力を/dev/nullにする
+        if !foreground && state.terminal.is_none() && streams.stdin.is_none() {
+            streams.stdin = File::open("/dev/null").ok().map(Into::into);
+        }
+
+        if commands.peek().is_some() {
+            // まだ後続にパイプ処理が残っている場合, 標準出力をpipeとして繋げる. 読み込み側は次のコマンドの標準入力になる
+            // 最後のコマンドの場合は標準出力を親プロセス(terminalの出力)のまま引き継ぐ. でないと結果が画面に出力されない
+            match pipe() {
+                Ok((reader, writer)) => {
+                    streams.next_stdin = Some(reader.as_raw_fd());
+                    previous_stdout = Some(reader);
+                    streams.stdout = Some(writer.into());
+                }
+                Err(e) => {
+                    eprintln!("shell: {}", describe(&e));
+                    processes.push(Process::finished(&command.to_string(), 1));
+                    break;
+                }
+            }
+        }
+
+        let last = commands.peek().is_none();
+        let process = match command {
+            AstCommand::Simple(command) => spawn_simple_command(
+                state, command, streams, &mut pgid, foreground, in_process, last,
+            ),
+            AstCommand::Compound(command, redirects) => {
+                let name = command.to_string();
+                spawn_subshell(state, &name, streams, &mut pgid, foreground, |state| {
+                    execute_compound(state, command, redirects)
+                })
+            }
+            // 関数の定義は実行するたびに登録し直す. 定義自体は必ず成功する
+            AstCommand::Function(function) => {
+                state
+                    .functions
+                    .insert(function.name.clone(), function.clone());
+                Process::finished(&function.name, 0)
+            }
+        };
+        processes.push(process);
+    }
+
+    Job::new(&pipeline.to_string(), pgid, processes)
+}
+
+fn spawn_simple_command(
+    state: &mut ShellState,
+    command: &SimpleCommand,
+    mut streams: Streams,
+    pgid: &mut Option<Pid>,
+    foreground: bool,
+    in_process: bool,
+    last: bool,
+) -> Process {
+    // パイプで繋いだ後にリダイレクトを適用するので, `cmd 2>&1 | less`のように書ける
+    // ファイルが開けなかった場合はそのコマンドだけ実行せずにエラーを表示する
+    if let Err(e) = apply_redirects(state, &command.redirects, &mut streams) {
+        eprintln!("shell: {}", describe(&e));
+        return Process::finished(&command.to_string(), 1);
+    }
+
+    // 単語を展開した上で, 先頭の単語をコマンド名, 残りを引数として扱う
+    let expanded = expand_words(state, &command.words)
+        .and_then(|words| Ok((words, expand_assignments(state, &command.assignments)?)));
+    let (words, assignments) = match expanded {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            eprintln!("shell: {}", e);
+            return Process::finished(&command.to_string(), 1);
+        }
+    };
+    let mut words = words.into_iter();
+    let name = match words.next() {
+        Some(name) => name,
+        // `FOO=1`のように代入だけの場合はシェル変数に代入する
+        // `> file`のようにリダイレクトだけの場合はファイルを作るだけで何も実行しない
+        // `x=$(cmd)`のように$(...)を含む場合は最後に実行した$(...)の終了ステータスを返す
+        None => {
+            for (name, value) in assignments {
+                state.set_var(&name, &value);
+            }
+            let has_command_subst = command.words.iter().any(Word::has_command_subst)
+                || command
+                    .assignments
+                    .iter()
+                    .any(|a| a.value.has_command_subst());
+            let status = if has_command_subst {
+                state.last_status
+            } else {
+                0
+            };
+            return Process::finished("", status);
+        }
+    };
+    let args: Vec<String> = words.collect();
+
+    // 関数は同じ名前のビルトインや外部コマンドより優先する
+    if let Some(function) = state.functions.get(&name).cloned() {
+        if !in_process {
+            return spawn_subshell(state, &name, streams, pgid, foreground, |state| {
+                with_assignments(state, &assignments, |state| {
+                    call_function(state, &function, args)
+                });
+            });
+        }
+        let status = match redirect_shell(streams) {
+            Ok(saved) => {
+                let status = with_assignments(state, &assignments, |state| {
+                    call_function(state, &function, args)
+                });
+                saved.restore();
+                status
+            }
+            Err(e) => {
+                eprintln!("shell: {}", describe(&e));
+                1
+            }
+        };
+        return Process::finished(&name, status);
+    }
+
+    // ビルトインはパイプラインの最後であればシェル自身の中で実行し, `echo a | read x`のxや`cd`の移動を残す
+    // パイプラインの途中やバックグラウンドの場合は, 前後のコマンドと並行して読み書きできるようにサブシェルで実行する
+    // execはシェル自身を置き換えるので, 1つだけのコマンドの場合以外はサブシェルを置き換える
+    if builtins::is_builtin(&name, &args) {
+        let current_shell = if name == "exec" {
+            in_process
+        } else {
+            foreground && last
+        };
+        if !current_shell {
+            return spawn_subshell(state, &name, streams, pgid, foreground, |state| {
+                state.last_status = run_builtin(state, &name, &args, &assignments);
+            });
+        }
+        let status = match redirect_shell(streams) {
+            Ok(saved) => {
+                let status = run_builtin(state, &name, &args, &assignments);
+                // `exec > file`のようにコマンドが無い場合は, リダイレクトをシェル自身に適用したまま戻さない
+                if name != "exec" || !args.is_empty() {
+                    saved.restore();
+                }
+                status
+            }
+            Err(e) => {
+                eprintln!("shell: {}", describe(&e));
+                1
+            }
+        };
+        return Process::finished(&name, status);
+    }
+
+    // `FOO=1 cmd`の代入はそのコマンドの環境変数にだけ反映する
+    let mut env = state.exported_vars();
+    env.extend(assignments);
+    match spawn_command(state, &name, &args, &env, streams, *pgid, foreground) {
+        Ok(child) => {
+            let pid = Pid::from_raw(child.id() as i32);
+            if state.terminal.is_some() && pgid.is_none() {
+                *pgid = Some(pid);
+            }
+            Process::spawned(pid, &name)
+        }
+        Err(e) => Process::finished(&name, report_spawn_error(&name, e)),
+    }
+}
+
+// パイプラインの途中やバックグラウンドの複合コマンド, 関数はサブシェルで実行する. パイプはサブシェルの0, 1番に繋ぐ
+fn spawn_subshell(
+    state: &mut ShellState,
+    name: &str,
+    mut streams: Streams,
+    pgid: &mut Option<Pid>,
+    foreground: bool,
+    f: impl FnOnce(&mut ShellState),
+) -> Process {
+    let result = fork_subshell(state, *pgid, foreground, |state| {
+        if let Some(fd) = streams.next_stdin.take() {
+            let _ = close(fd);
+        }
+        if let Err(e) = redirect_shell(streams) {
+            eprintln!("shell: {}", describe(&e));
+            return 1;
+        }
+        f(state);
+        state.exit_status.unwrap_or(state.last_status)
+    });
+    match result {
+        Ok(pid) => {
+            if state.terminal.is_some() && pgid.is_none() {
+                *pgid = Some(pid);
+            }
+            Process::spawned(pid, name)
+        }
+        Err(e) => {
+            eprintln!("shell: fork: {}", e.desc());
+            Process::finished(name, 1)
+        }
+    }
+}
+
+// 関数の中では引数が位置パラメータになる. 呼び出し元の位置パラメータやlocalで隠した変数は戻る時に元に戻す
+fn call_function(state: &mut ShellState, function: &FunctionDef, args: Vec<String>) -> i32 {
+    let positional = mem::replace(&mut state.positional, args);
+    // 呼び出し元のループを関数の中からbreakすることはできない
+    let loop_depth = mem::take(&mut state.loop_depth);
+    state.push_local_scope();
+
+    execute_compound(state, &function.body, &function.redirects);
+    if state.flow == Some(Flow::Return) {
+        state.flow = None;
+    }
+
+    state.pop_local_scope();
+    state.loop_depth = loop_depth;
+    state.positional = positional;
+    state.last_status
+}
+
+fn expand_assignments(
+    state: &mut ShellState,
+    assignments: &[Assignment],
+) -> Result<Vec<(String, String)>, ExpandError> {
+    assignments
+        .iter()
+        .map(|a| Ok((a.name.clone(), expand_assignment(state, &a.value)?)))
+        .collect()
+}
+
+// ビルトインの場合も`FOO=1 builtin`の代入はそのコマンドの実行中だけ有効にする
+// 入出力はredirect_shellでパイプやリダイレクトの先に付け替えた後のシェル自身のfd 0, 1, 2
+fn run_builtin(
+    state: &mut ShellState,
+    name: &str,
+    args: &[String],
+    assignments: &[(String, String)],
+) -> i32 {
+    let stdin = io::stdin();
+    let mut stdio = builtins::Stdio {
+        stdin: stdin.as_fd(),
+        stdout: &mut io::stdout(),
+        stderr: &mut io::stderr(),
+    };
+    with_assignments(state, assignments, |state| {
+        builtins::run(state, name, args, &mut stdio).unwrap_or(0)
+    })
+}
+
+// `FOO=1 f`の代入をexportした変数としてfの実行中だけ有効にする
+fn with_assignments<T>(
+    state: &mut ShellState,
+    assignments: &[(String, String)],
+    f: impl FnOnce(&mut ShellState) -> T,
+) -> T {
+    let mut saved = Vec::new();
+    for (var_name, value) in assignments {
+        saved.push((var_name, state.unset_var(var_name)));
+        state.export_var(var_name, Some(value));
+    }
+    let result = f(state);
+    // 実行中に変数が書き換えられていても代入前の状態に戻す
+    for (var_name, var) in saved.into_iter().rev() {
+        match var {
+            Some(var) => {
+                state.vars.insert(var_name.clone(), var);
+            }
+            None => {
+                state.unset_var(var_name);
+            }
+        }
+    }
+    result
+}
+
+fn spawn_command(
+    state: &ShellState,
+    name: &str,
+    args: &[String],
+    env: &[(String, String)],
+    streams: Streams,
+    pgid: Option<Pid>,
+    foreground: bool,
+) -> io::Result<Child> {
+    let (stdin, stdout, stderr) = streams.into_stdio();
+    let mut command = Command::new(name);
+    command
+        .args(args)
+        .env_clear()
+        .envs(env.iter().cloned())
+        .stdin(stdin)
+        .stdout(stdout)
+        .stderr(stderr);
+
+    if let Some(terminal) = &state.terminal {
+        // パイプラインの全てのコマンドを同じプロセスグループに入れる. 0の場合は自分のプロセスIDで新しいグループを作る
+        command.process_group(pgid.map_or(0, |pgid| pgid.as_raw()));
+        let tty = foreground.then(|| terminal.raw_fd());
+        let pre_exec = move || {
+            // 親プロセスと子プロセスのどちらが先に動いても端末がジョブに渡っているように子プロセス側でもtcsetpgrpする
+            // SIGTTOUを無視している間に行う必要があるので, シグナルの設定を戻す前に呼ぶ
+            if let Some(fd) = tty {
+                let _ = tcsetpgrp(unsafe { BorrowedFd::borrow_raw(fd) }, getpgrp());
+            }
+            restore_default_signals().map_err(io::Error::from)
+        };
+        // pre_execの中ではasync-signal-safeな処理しか行っていない
+        unsafe {
+            command.pre_exec(pre_exec);
+        }
+    }
+
+    command.spawn()
+}
+
+// フォアグラウンドのジョブに端末を渡して, 終了するか停止するまで待つ
+// 停止した場合(Ctrl-Z)はジョブテーブルに登録して後からfgやbgで再開できるようにする
+pub fn wait_for_job(state: &mut ShellState, mut job: Job) -> i32 {
+    if let (Some(terminal), Some(pgid)) = (&state.terminal, job.pgid) {
+        terminal.give_to(pgid);
+    }
+    job.wait();
+    if let Some(terminal) = &state.terminal {
+        terminal.take_back();
+    }
+
+    // Ctrl-Cでジョブが終了した場合, 端末には^Cだけが表示されているので改行してからプロンプトを出す
+    let interrupted = 128 + Signal::SIGINT as i32;
+    if state.terminal.is_some() && job.statuses().contains(&interrupted) {
+        eprintln!();
+        // `while true; do sleep 1; done`のようなループはCtrl-Cで残りの繰り返しごと止める
+        if state.loop_depth > 0 {
+            state.flow = Some(Flow::Break(state.loop_depth));
+        }
+    }
+
+    let status = if job.is_stopped() {
+        let id = state.jobs.add(job);
+        if let Some(job) = state.jobs.get(id) {
+            eprintln!();
+            eprintln!("{}", state.jobs.format(job));
+        }
+        128 + Signal::SIGTSTP as i32
+    } else {
+        let statuses = job.statuses();
+        let status = pipeline_status(state, &statuses);
+        state.pipestatus = statuses;
+        status
+    };
+
+    state.last_status = status;
+    status
+}
+
+// pipefailが有効な場合は失敗したコマンドのうち一番右のものの終了ステータスをパイプライン全体の結果とする
+pub fn pipeline_status(state: &ShellState, statuses: &[i32]) -> i32 {
+    if state.options.pipefail {
+        statuses
+            .iter()
+            .rev()
+            .find(|&&s| s != 0)
+            .copied()
+            .unwrap_or(0)
+    } else {
+        statuses.last().copied().unwrap_or(0)
+    }
+}
+
+// 子プロセスをforkしてその中でfを実行する. 子プロセスはfの返り値を終了ステータスとして終了する
+fn fork_subshell(
+    state: &mut ShellState,
+    pgid: Option<Pid>,
+    foreground: bool,
+    f: impl FnOnce(&mut ShellState) -> i32,
+) -> nix::Result<Pid> {
+    // バッファに残っている出力が子プロセスでも出力されてしまわないように先に吐き出しておく
+    let _ = io::stdout().flush();
+
+    // forkの時点でシェルはシングルスレッドなので, 子プロセスで何をしても問題ない
+    match unsafe { fork() }? {
+        ForkResult::Child => {
+            if let Some(terminal) = &state.terminal {
+                let _ = setpgid(Pid::from_raw(0), pgid.unwrap_or(Pid::from_raw(0)));
+                // spawn_commandと同じく, フォアグラウンドの場合は子プロセス側でも端末を受け取っておく
+                if foreground {
+                    terminal.give_to(getpgrp());
+                }
+                let _ = restore_default_signals();
+            }
+            restore_sigpipe();
+            // サブシェルの中ではジョブ制御を行わない
+            state.terminal = None;
+            state.jobs = JobTable::default();
+
+            let status = f(state);
+            let _ = io::stdout().flush();
+            process::exit(status);
+        }
+        ForkResult::Parent { child } => {
+            if state.terminal.is_some() {
+                let _ = setpgid(child, pgid.unwrap_or(child));
+            }
+            Ok(child)
+        }
+    }
+}
+
+// コマンドを起動できなかった理由を表示して, 終了ステータスを返す
+fn report_spawn_error(command: &str, error: io::Error) -> i32 {
+    let e = ShellError::Spawn {
+        command: command.to_string(),
+        error,
+    };
+    eprintln!("shell: {}", e);
+    e.status()
+}
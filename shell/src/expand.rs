@@ -0,0 +1,905 @@
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use dirs::home_dir;
+use glob::{MatchOptions, Pattern};
+use nix::unistd::User;
+
+use crate::{
+    arith::{self, ArithError},
+    error::describe,
+    executor::capture_output,
+    parser::{parse_with_aliases, ParseError},
+    state::{is_valid_name, ShellState},
+    token::{ParamExpansion, ParamOp, Word, WordPart},
+};
+
+// IFSが設定されていない場合の区切り文字
+const DEFAULT_IFS: &str = " \t\n";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExpandError {
+    // ${name:?message}でnameが設定されていなかった
+    ParamUnset { name: String, message: String },
+    // ${1:=word}のように代入できないパラメータに代入しようとした
+    BadAssignment(String),
+    // $(...)の中身が文法的に正しくない
+    Syntax(ParseError),
+    // $(...)を実行するためのパイプやforkに失敗した
+    CommandSubst(String),
+    // $((...))の式が評価できなかった. exprは展開後の式
+    Arith { expr: String, error: ArithError },
+}
+
+impl Display for ExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpandError::ParamUnset { name, message } if message.is_empty() => {
+                write!(f, "{}: parameter null or not set", name)
+            }
+            ExpandError::ParamUnset { name, message } => write!(f, "{}: {}", name, message),
+            ExpandError::BadAssignment(name) => write!(f, "${}: cannot assign in this way", name),
+            ExpandError::Syntax(e) => write!(f, "{}", e),
+            ExpandError::CommandSubst(message) => {
+                write!(f, "command substitution: {}", message)
+            }
+            ExpandError::Arith { expr, error } => write!(f, "{}: {}", expr.trim(), error),
+        }
+    }
+}
+
+// コマンドの単語を展開して引数のリストにする
+// クォートされていないパラメータの展開結果はIFSで分割されるので, 1つの単語が複数の引数になったり, 消えたりする
+// 分割した後の各引数にクォートされていない*や?が含まれていれば, マッチするファイル名の一覧に置き換える
+pub fn expand_words(state: &mut ShellState, words: &[Word]) -> Result<Vec<String>, ExpandError> {
+    let mut result = Vec::new();
+    for word in words {
+        let mut fields = Fields::new(Some(ifs(state)));
+        expand_parts(state, &word.parts, false, Tilde::Word, &mut fields)?;
+        for field in fields.finish() {
+            result.extend(expand_glob(field));
+        }
+    }
+    Ok(result)
+}
+
+// リダイレクト先のように, 分割もファイル名の展開もせずに1つの文字列として展開する
+pub fn expand_word(state: &mut ShellState, word: &Word) -> Result<String, ExpandError> {
+    expand_single(state, &word.parts, Tilde::Word)
+}
+
+// `PATH=~/bin:~/.cargo/bin`のように, 代入する値では:の直後の~も展開する
+pub fn expand_assignment(state: &mut ShellState, word: &Word) -> Result<String, ExpandError> {
+    expand_single(state, &word.parts, Tilde::Assignment)
+}
+
+// caseのパターンとしてwordを展開し, valueにマッチするかを返す
+// ファイル名の展開と違って*は/や先頭の.にもマッチする. 不正なパターンは文字列として比較する
+pub fn matches_pattern(
+    state: &mut ShellState,
+    word: &Word,
+    value: &str,
+) -> Result<bool, ExpandError> {
+    let mut fields = Fields::new(None);
+    expand_parts(state, &word.parts, false, Tilde::Word, &mut fields)?;
+    let fields = fields.finish();
+    let texts: Vec<&str> = fields.iter().map(|field| field.text.as_str()).collect();
+    let patterns: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let chars: Vec<(char, bool)> = field.text.chars().zip(field.quoted.clone()).collect();
+            pattern_text(&chars)
+        })
+        .collect();
+    Ok(match Pattern::new(&patterns.join(" ")) {
+        Ok(pattern) => pattern.matches(value),
+        Err(_) => texts.join(" ") == value,
+    })
+}
+
+fn expand_single(
+    state: &mut ShellState,
+    parts: &[WordPart],
+    tilde: Tilde,
+) -> Result<String, ExpandError> {
+    let mut fields = Fields::new(None);
+    expand_parts(state, parts, true, tilde, &mut fields)?;
+    // `$@`は複数の引数に分かれるので空白で繋げる
+    let texts: Vec<String> = fields
+        .finish()
+        .into_iter()
+        .map(|field| field.text)
+        .collect();
+    Ok(texts.join(" "))
+}
+
+// どこにある~を展開するか
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tilde {
+    // ダブルクォートの中では展開しない
+    None,
+    // 単語の先頭の~だけを展開する
+    Word,
+    // 単語の先頭と:の直後の~を展開する
+    Assignment,
+}
+
+fn ifs(state: &ShellState) -> String {
+    state.var("IFS").unwrap_or(DEFAULT_IFS).to_string()
+}
+
+fn expand_parts(
+    state: &mut ShellState,
+    parts: &[WordPart],
+    quoted: bool,
+    tilde: Tilde,
+    fields: &mut Fields,
+) -> Result<(), ExpandError> {
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            WordPart::Literal(s) if tilde != Tilde::None => {
+                // `~"foo"`のように~の後ろがクォートされている場合はユーザー名の途中なので展開しない
+                let followed = i + 1 < parts.len();
+                for (text, tilde_quoted) in expand_tilde(state, s, i == 0, tilde, followed) {
+                    fields.push_str(&text, quoted || tilde_quoted);
+                }
+            }
+            WordPart::Literal(s) => fields.push_str(s, quoted),
+            WordPart::Quoted(s) => fields.push_str(s, true),
+            // 位置パラメータが無い場合の`"$@"`は空文字列ではなく引数自体が無くなる
+            WordPart::DoubleQuoted(parts)
+                if is_all_params(parts) && state.positional.is_empty() => {}
+            WordPart::DoubleQuoted(parts) => {
+                // `""`のように中身が空でも空文字列の引数として残す
+                fields.push_str("", true);
+                expand_parts(state, parts, true, Tilde::None, fields)?;
+            }
+            WordPart::Param(param)
+                if param.op == ParamOp::Plain && (param.name == "@" || param.name == "*") =>
+            {
+                expand_positional(state, &param.name, quoted, fields);
+            }
+            WordPart::Param(param) => {
+                let value = expand_param(state, param)?;
+                if quoted {
+                    fields.push_str(&value, true);
+                } else {
+                    fields.push_split(&value);
+                }
+            }
+            WordPart::CommandSubst(source) => {
+                let output = command_substitution(state, source)?;
+                if quoted {
+                    fields.push_str(&output, true);
+                } else {
+                    fields.push_split(&output);
+                }
+            }
+            WordPart::Arith(parts) => {
+                let expr = expand_single(state, parts, Tilde::None)?;
+                let value = arith::evaluate(state, &expr)
+                    .map_err(|error| ExpandError::Arith { expr, error })?;
+                if quoted {
+                    fields.push_str(&value.to_string(), true);
+                } else {
+                    fields.push_split(&value.to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// $(...)の中身をパースしてサブシェルで実行し, 標準出力から末尾の改行を取り除いたものを返す
+fn command_substitution(state: &mut ShellState, source: &str) -> Result<String, ExpandError> {
+    let list = match parse_with_aliases(source, &state.aliases) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Ok(String::new()),
+        Err(e) => return Err(ExpandError::Syntax(e)),
+    };
+    let output =
+        capture_output(state, &list).map_err(|e| ExpandError::CommandSubst(describe(&e)))?;
+    Ok(output.trim_end_matches('\n').to_string())
+}
+
+fn is_all_params(parts: &[WordPart]) -> bool {
+    matches!(parts, [WordPart::Param(param)] if param.name == "@" && param.op == ParamOp::Plain)
+}
+
+// `"$@"`は位置パラメータをそれぞれ別の引数に, `"$*"`はIFSの最初の文字で繋げた1つの引数にする
+// クォートされていない場合はどちらも各パラメータを更にIFSで分割する
+fn expand_positional(state: &ShellState, name: &str, quoted: bool, fields: &mut Fields) {
+    let params = &state.positional;
+    if quoted && name == "*" {
+        let separator: String = ifs(state).chars().take(1).collect();
+        fields.push_str(&params.join(&separator), true);
+        return;
+    }
+
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            fields.end_field(quoted);
+        }
+        if quoted {
+            fields.push_str(param, true);
+        } else {
+            fields.push_split(param);
+        }
+    }
+}
+
+// クォートされていない文字列の中の~を展開する. 展開結果は分割やファイル名の展開をしないのでクォートされたものとして返す
+fn expand_tilde(
+    state: &ShellState,
+    s: &str,
+    at_start: bool,
+    tilde: Tilde,
+    followed: bool,
+) -> Vec<(String, bool)> {
+    let segments: Vec<&str> = match tilde {
+        Tilde::Assignment => s.split(':').collect(),
+        _ => vec![s],
+    };
+    let last = segments.len() - 1;
+
+    let mut pieces = Vec::new();
+    for (i, segment) in segments.into_iter().enumerate() {
+        if i > 0 {
+            pieces.push((":".to_string(), false));
+        }
+        // ~から最初の/までがユーザー名
+        let (prefix, rest) = segment.split_at(segment.find('/').unwrap_or(segment.len()));
+        let dir = match prefix.strip_prefix('~') {
+            Some(user) if (at_start || i > 0) && !(i == last && rest.is_empty() && followed) => {
+                tilde_dir(state, user)
+            }
+            _ => None,
+        };
+        match dir {
+            Some(dir) => {
+                pieces.push((dir, true));
+                pieces.push((rest.to_string(), false));
+            }
+            None => pieces.push((segment.to_string(), false)),
+        }
+    }
+    pieces
+}
+
+// ~はホームディレクトリ, ~+はカレントディレクトリ, ~-は直前にいたディレクトリ, ~userはuserのホームディレクトリ
+// 展開できない場合はNoneを返し, ~userのまま残す
+fn tilde_dir(state: &ShellState, user: &str) -> Option<String> {
+    match user {
+        "" => state
+            .var("HOME")
+            .map(str::to_string)
+            .or_else(|| home_dir().map(|dir| dir.display().to_string())),
+        "+" => state
+            .var("PWD")
+            .map(str::to_string)
+            .or_else(|| env::current_dir().ok().map(|dir| dir.display().to_string())),
+        "-" => state.var("OLDPWD").map(str::to_string),
+        user => User::from_name(user)
+            .ok()
+            .flatten()
+            .map(|user| user.dir.display().to_string()),
+    }
+}
+
+// プロンプトに表示するためにホームディレクトリ以下のパスを~で始まる形に縮める
+// パスの要素単位で比較するので, /home/userに対して/home/user2や/x/home/userは縮めない
+pub fn abbreviate_home(path: &Path, home: &Path) -> String {
+    // ホームディレクトリが/の場合に全てのパスが~で始まってしまわないようにする
+    if home.parent().is_none() {
+        return path.display().to_string();
+    }
+    match path.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+fn expand_param(state: &mut ShellState, param: &ParamExpansion) -> Result<String, ExpandError> {
+    let value = lookup_param(state, &param.name);
+    // :が付いている場合は空文字列も未設定と同じように扱う
+    let is_set = |colon: bool| match &value {
+        Some(value) => !(colon && value.is_empty()),
+        None => false,
+    };
+
+    match &param.op {
+        ParamOp::Plain => Ok(value.unwrap_or_default()),
+        ParamOp::Length => Ok(value.unwrap_or_default().chars().count().to_string()),
+        ParamOp::Default(colon, word) => {
+            if is_set(*colon) {
+                Ok(value.unwrap_or_default())
+            } else {
+                expand_word(state, word)
+            }
+        }
+        ParamOp::Assign(colon, word) => {
+            if is_set(*colon) {
+                return Ok(value.unwrap_or_default());
+            }
+            if !is_valid_name(&param.name) {
+                return Err(ExpandError::BadAssignment(param.name.clone()));
+            }
+            let value = expand_word(state, word)?;
+            state.set_var(&param.name, &value);
+            Ok(value)
+        }
+        ParamOp::Alternative(colon, word) => {
+            if is_set(*colon) {
+                expand_word(state, word)
+            } else {
+                Ok(String::new())
+            }
+        }
+        ParamOp::Error(colon, word) => {
+            if is_set(*colon) {
+                return Ok(value.unwrap_or_default());
+            }
+            // 対話モードでなければ, エラーを表示した後にシェル自体を終了する. サブシェルの中ならサブシェルだけ
+            if !state.interactive {
+                state.exit_status = Some(1);
+            }
+            Err(ExpandError::ParamUnset {
+                name: param.name.clone(),
+                message: expand_word(state, word)?,
+            })
+        }
+    }
+}
+
+// パラメータの値を返す. 設定されていない場合はNone
+fn lookup_param(state: &ShellState, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(state.last_status.to_string()),
+        "$" => Some(state.shell_pid.to_string()),
+        "!" => state.last_background_pid.map(|pid| pid.to_string()),
+        "#" => Some(state.positional.len().to_string()),
+        "@" | "*" => Some(state.positional.join(" ")),
+        "0" => Some(state.script_name.clone()),
+        // 配列は無いので, 各コマンドの終了ステータスを空白区切りで並べる. `for s in $PIPESTATUS`のように使える
+        "PIPESTATUS" => {
+            let statuses: Vec<String> = state.pipestatus.iter().map(i32::to_string).collect();
+            Some(statuses.join(" "))
+        }
+        _ if name.chars().all(|c| c.is_ascii_digit()) => {
+            let n: usize = name.parse().ok()?;
+            state.positional.get(n.checked_sub(1)?).cloned()
+        }
+        _ => state.var(name).map(str::to_string),
+    }
+}
+
+// `*.rs`のようなパターンをマッチするファイル名に展開する. 何もマッチしなければパターンをそのまま残す
+fn expand_glob(field: Field) -> Vec<String> {
+    if !field.has_glob() {
+        return vec![field.text];
+    }
+    let Some(segments) = field.segments() else {
+        // `[`だけのような不正なパターンはただの文字列として扱う
+        return vec![field.text];
+    };
+
+    // `*/`のように/で終わるパターンはディレクトリにだけマッチする
+    let dir_only = field.text.ends_with('/');
+    let base = if field.text.starts_with('/') {
+        PathBuf::from("/")
+    } else {
+        PathBuf::new()
+    };
+    let mut matches = Vec::new();
+    glob_dir(&base, &segments, &mut matches);
+
+    let mut matches: Vec<String> = matches
+        .into_iter()
+        .filter(|path| !dir_only || path.is_dir())
+        .map(|path| {
+            let path = path.to_string_lossy().into_owned();
+            if dir_only {
+                path + "/"
+            } else {
+                path
+            }
+        })
+        .collect();
+    matches.sort();
+    matches.dedup();
+
+    if matches.is_empty() {
+        vec![field.text]
+    } else {
+        matches
+    }
+}
+
+// パターンを/で区切った1つ分
+enum Segment {
+    // ワイルドカードを含まない部分はディレクトリを読まずにそのまま繋げる
+    Literal(String),
+    // `.`で始まるファイルはパターンも`.`で始まる場合だけマッチさせるので, そのためのフラグも持っておく
+    Pattern(Pattern, bool),
+    // `**`. 0個以上のディレクトリにマッチする
+    Recursive,
+}
+
+// dirの中からsegmentsにマッチするパスを探してmatchesに追加する
+fn glob_dir(dir: &Path, segments: &[Segment], matches: &mut Vec<PathBuf>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        matches.push(dir.to_path_buf());
+        return;
+    };
+
+    match segment {
+        Segment::Literal(name) => {
+            let path = dir.join(name);
+            if path.symlink_metadata().is_ok() {
+                glob_dir(&path, rest, matches);
+            }
+        }
+        Segment::Pattern(pattern, dot) => {
+            let options = MatchOptions {
+                case_sensitive: true,
+                require_literal_separator: true,
+                require_literal_leading_dot: false,
+            };
+            for name in read_dir_names(dir) {
+                if (*dot || !name.starts_with('.')) && pattern.matches_with(&name, options) {
+                    glob_dir(&dir.join(name), rest, matches);
+                }
+            }
+        }
+        Segment::Recursive => {
+            // `**/*.rs`はカレントディレクトリの*.rsにもマッチする. `**`だけの場合は全てのファイルにマッチする
+            if !rest.is_empty() {
+                glob_dir(dir, rest, matches);
+            }
+            for name in read_dir_names(dir) {
+                if name.starts_with('.') {
+                    continue;
+                }
+                let path = dir.join(name);
+                if rest.is_empty() {
+                    matches.push(path.clone());
+                }
+                // シンボリックリンクを辿ると無限にループすることがあるので, 本物のディレクトリだけを再帰的に辿る
+                if path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+                    glob_dir(&path, segments, matches);
+                }
+            }
+        }
+    }
+}
+
+// ディレクトリの中のファイル名の一覧. 読めないディレクトリは空として扱う
+fn read_dir_names(dir: &Path) -> Vec<String> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// 展開中の1つの引数
+#[derive(Debug, Default)]
+struct Field {
+    // クォートを取り除いた文字列
+    text: String,
+    // textの各文字がクォートされていたかどうか. クォートされた*や?はファイル名の展開に使わない
+    quoted: Vec<bool>,
+}
+
+impl Field {
+    fn push(&mut self, c: char, quoted: bool) {
+        self.text.push(c);
+        self.quoted.push(quoted);
+    }
+
+    // クォートされていない*, ?, [が含まれているかどうか
+    fn has_glob(&self) -> bool {
+        self.text
+            .chars()
+            .zip(&self.quoted)
+            .any(|(c, quoted)| !quoted && matches!(c, '*' | '?' | '['))
+    }
+
+    // パターンを/で区切る. 不正なパターンが含まれていればNoneを返す
+    fn segments(&self) -> Option<Vec<Segment>> {
+        let mut segments = Vec::new();
+        let chars: Vec<(char, bool)> = self.text.chars().zip(self.quoted.clone()).collect();
+        for component in chars.split(|&(c, _)| c == '/') {
+            if component.is_empty() {
+                continue;
+            }
+            let text: String = component.iter().map(|&(c, _)| c).collect();
+            let is_glob = |&(c, quoted): &(char, bool)| !quoted && matches!(c, '*' | '?' | '[');
+            if !component.iter().any(is_glob) {
+                segments.push(Segment::Literal(text));
+            } else if component == [('*', false), ('*', false)] {
+                segments.push(Segment::Recursive);
+            } else {
+                let pattern = Pattern::new(&pattern_text(component)).ok()?;
+                segments.push(Segment::Pattern(pattern, text.starts_with('.')));
+            }
+        }
+        Some(segments)
+    }
+}
+
+// クォートされていた文字はエスケープしてただの文字としてマッチさせる
+fn pattern_text(chars: &[(char, bool)]) -> String {
+    chars
+        .iter()
+        .map(|&(c, quoted)| {
+            if quoted {
+                Pattern::escape(&c.to_string())
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+// 展開結果を引数ごとに区切りながら組み立てる
+struct Fields {
+    // Noneの場合は分割しない
+    ifs: Option<String>,
+    fields: Vec<Field>,
+    // 組み立て中の引数. クォートされていない空の展開結果だけの場合は引数自体を作らないのでNoneのままにする
+    current: Option<Field>,
+    // 直前に空白文字で引数を区切ったかどうか. `a , b`の", "を1つの区切りとして扱うために使う
+    after_whitespace: bool,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>) -> Self {
+        Fields {
+            ifs,
+            fields: Vec::new(),
+            current: None,
+            after_whitespace: false,
+        }
+    }
+
+    fn push_str(&mut self, s: &str, quoted: bool) {
+        let field = self.current.get_or_insert_with(Field::default);
+        for c in s.chars() {
+            field.push(c, quoted);
+        }
+        self.after_whitespace = false;
+    }
+
+    // IFSに含まれる文字で区切りながら追加する
+    // IFSのうち空白文字は連続していても1つの区切りとして扱い, 先頭や末尾にあれば無視する
+    // それ以外の文字は1文字ごとに区切りになるので, `a,,b`は"a", "", "b"になる
+    fn push_split(&mut self, s: &str) {
+        let ifs = match &self.ifs {
+            Some(ifs) if !ifs.is_empty() => ifs.clone(),
+            _ => {
+                if !s.is_empty() {
+                    self.push_str(s, false);
+                }
+                return;
+            }
+        };
+
+        for c in s.chars() {
+            if !ifs.contains(c) {
+                self.current
+                    .get_or_insert_with(Field::default)
+                    .push(c, false);
+                self.after_whitespace = false;
+            } else if c.is_whitespace() {
+                if let Some(field) = self.current.take() {
+                    self.fields.push(field);
+                    self.after_whitespace = true;
+                }
+            } else {
+                match self.current.take() {
+                    Some(field) => self.fields.push(field),
+                    None if self.after_whitespace => {}
+                    None => self.fields.push(Field::default()),
+                }
+                self.after_whitespace = false;
+            }
+        }
+    }
+
+    // 組み立て中の引数をそこで区切る. keep_emptyがfalseの場合, 空の引数は作らない
+    fn end_field(&mut self, keep_empty: bool) {
+        match self.current.take() {
+            Some(field) => self.fields.push(field),
+            None if keep_empty => self.fields.push(Field::default()),
+            None => {}
+        }
+        self.after_whitespace = false;
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        self.fields.extend(self.current.take());
+        self.fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::token::Token;
+
+    fn expand(state: &mut ShellState, input: &str) -> Vec<String> {
+        let words: Vec<Word> = tokenize(input)
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect();
+        expand_words(state, &words).unwrap()
+    }
+
+    #[test]
+    fn test_expand_status() {
+        let mut state = ShellState {
+            last_status: 127,
+            ..Default::default()
+        };
+        assert_eq!(
+            expand(&mut state, r#"echo $? "status: $?" '$?' \$?"#),
+            vec!["echo", "127", "status: 127", "$?", "$?"]
+        );
+
+        state.pipestatus = vec![1, 0, 141];
+        assert_eq!(
+            expand(&mut state, r#"$PIPESTATUS "$PIPESTATUS""#),
+            vec!["1", "0", "141", "1 0 141"]
+        );
+    }
+
+    #[test]
+    fn test_expand_vars() {
+        let mut state = ShellState::default();
+        state.set_var("NAME", "world");
+        state.set_var("EMPTY", "");
+        assert_eq!(
+            expand(
+                &mut state,
+                r#"$NAME ${NAME}s "$NAME's" '$NAME' ${#NAME} $UNSET"#
+            ),
+            vec!["world", "worlds", "world's", "$NAME", "5"]
+        );
+        assert_eq!(
+            expand(
+                &mut state,
+                "${UNSET:-default} ${EMPTY:-default} ${EMPTY-default}."
+            ),
+            vec!["default", "default", "."]
+        );
+        assert_eq!(
+            expand(&mut state, "${NAME:+set} ${UNSET:+set}. ${NEW:=new} $NEW"),
+            vec!["set", ".", "new", "new"]
+        );
+        assert_eq!(state.var("NEW"), Some("new"));
+
+        let words = match tokenize("${UNSET:?'is required'}").unwrap().remove(0) {
+            Token::Word(word) => word,
+            _ => unreachable!(),
+        };
+        let error = expand_word(&mut state, &words).unwrap_err();
+        assert_eq!(error.to_string(), "UNSET: is required");
+    }
+
+    #[test]
+    fn test_expand_arith() {
+        let mut state = ShellState::default();
+        state.set_var("i", "3");
+        state.positional = vec!["4".to_string()];
+        assert_eq!(
+            expand(
+                &mut state,
+                "$((i + 1)) \"$(( (i + $1) * 2 ))\" x$((i += ${#i}))"
+            ),
+            vec!["4", "14", "x4"]
+        );
+        assert_eq!(state.var("i"), Some("4"));
+
+        let word = match tokenize("$((1 / (i - 4)))").unwrap().remove(0) {
+            Token::Word(word) => word,
+            _ => unreachable!(),
+        };
+        let error = expand_word(&mut state, &word).unwrap_err();
+        assert_eq!(error.to_string(), "1 / (i - 4): division by 0");
+    }
+
+    #[test]
+    fn test_expand_positional() {
+        let mut state = ShellState {
+            script_name: "script.sh".to_string(),
+            positional: vec!["a b".to_string(), "".to_string(), "c".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            expand(&mut state, "$0 $# $1 ${3}"),
+            vec!["script.sh", "3", "a", "b", "c"]
+        );
+        assert_eq!(expand(&mut state, r#""$@""#), vec!["a b", "", "c"]);
+        assert_eq!(expand(&mut state, r#""x$@y""#), vec!["xa b", "", "cy"]);
+        assert_eq!(expand(&mut state, "$@"), vec!["a", "b", "c"]);
+        assert_eq!(expand(&mut state, r#""$*""#), vec!["a b  c"]);
+        state.set_var("IFS", ",");
+        assert_eq!(expand(&mut state, r#""$*""#), vec!["a b,,c"]);
+        assert_eq!(expand(&mut state, "$4 ${10}"), Vec::<String>::new());
+
+        state.positional.clear();
+        assert_eq!(expand(&mut state, r#""$@" "$*""#), vec![""]);
+    }
+
+    #[test]
+    fn test_expand_field_splitting() {
+        let mut state = ShellState::default();
+        state.set_var("LIST", "  a b\tc  ");
+        assert_eq!(
+            expand(&mut state, "x${LIST}y"),
+            vec!["x", "a", "b", "c", "y"]
+        );
+        assert_eq!(expand(&mut state, r#""$LIST""#), vec!["  a b\tc  "]);
+        assert_eq!(expand(&mut state, r#"$EMPTY "" "$EMPTY""#), vec!["", ""]);
+
+        state.set_var("IFS", ",");
+        state.set_var("CSV", "a,,b ,c");
+        assert_eq!(expand(&mut state, "$CSV"), vec!["a", "", "b ", "c"]);
+
+        state.set_var("IFS", " ,");
+        state.set_var("CSV", "a , b,c");
+        assert_eq!(expand(&mut state, "$CSV"), vec!["a", "b", "c"]);
+
+        state.set_var("IFS", "");
+        assert_eq!(expand(&mut state, "$LIST"), vec!["  a b\tc  "]);
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        for path in [
+            "a.rs",
+            "b.rs",
+            "c.txt",
+            ".hidden.rs",
+            "sub/d.rs",
+            "sub/deep/e.rs",
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let dir = dir.to_str().unwrap().to_string();
+        let mut state = ShellState::default();
+        let mut glob = |pattern: &str| -> Vec<String> {
+            expand(&mut state, &format!("'{}'/{}", dir, pattern))
+                .into_iter()
+                .map(|path| path[dir.len() + 1..].to_string())
+                .collect()
+        };
+
+        assert_eq!(glob("*.rs"), vec!["a.rs", "b.rs"]);
+        assert_eq!(glob("?.*"), vec!["a.rs", "b.rs", "c.txt"]);
+        assert_eq!(glob("[ac].*"), vec!["a.rs", "c.txt"]);
+        assert_eq!(glob(".*.rs"), vec![".hidden.rs"]);
+        assert_eq!(
+            glob("**/*.rs"),
+            vec!["a.rs", "b.rs", "sub/d.rs", "sub/deep/e.rs"]
+        );
+        // マッチしない場合やクォートされている場合はそのまま
+        assert_eq!(glob("*.md"), vec!["*.md"]);
+        assert_eq!(glob("'*.rs'"), vec!["*.rs"]);
+        assert_eq!(glob("\\*.rs"), vec!["*.rs"]);
+        assert_eq!(glob("*/"), vec!["sub/"]);
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let mut state = ShellState::default();
+        state.set_var("HOME", "/home/me");
+        state.set_var("PWD", "/work");
+        state.set_var("OLDPWD", "/prev");
+        assert_eq!(
+            expand(
+                &mut state,
+                "~ ~/src ~+ ~-/x a~ '~' \"~\" ~\"\" ~nosuchuser/x"
+            ),
+            vec![
+                "/home/me",
+                "/home/me/src",
+                "/work",
+                "/prev/x",
+                "a~",
+                "~",
+                "~",
+                "~",
+                "~nosuchuser/x"
+            ]
+        );
+        // rootのホームディレクトリはOSによって違うので, passwdから引いた値と比べる
+        if let Ok(Some(root)) = User::from_name("root") {
+            assert_eq!(
+                expand(&mut state, "~root/x"),
+                vec![root.dir.join("x").display().to_string()]
+            );
+        }
+
+        let mut assign = |input: &str| {
+            let word = match tokenize(input).unwrap().remove(0) {
+                Token::Word(word) => word,
+                _ => unreachable!(),
+            };
+            (
+                expand_word(&mut state, &word).unwrap(),
+                expand_assignment(&mut state, &word).unwrap(),
+            )
+        };
+        assert_eq!(
+            assign("~/bin:~/.cargo/bin"),
+            (
+                "/home/me/bin:~/.cargo/bin".to_string(),
+                "/home/me/bin:/home/me/.cargo/bin".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        let mut state = ShellState::default();
+        state.set_var("P", "*.rs");
+        let mut matches = |pattern: &str, value: &str| {
+            let word = match tokenize(pattern).unwrap().remove(0) {
+                Token::Word(word) => word,
+                token => panic!("unexpected token: {}", token),
+            };
+            matches_pattern(&mut state, &word, value).unwrap()
+        };
+
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(matches("*", ".hidden"));
+        assert!(matches("[abc]?", "b1"));
+        assert!(!matches("[abc]?", "d1"));
+        assert!(matches("$P", "lib.rs"));
+        // クォートされた部分はただの文字として比較する
+        assert!(matches("'*'", "*"));
+        assert!(!matches("\"*\".rs", "lib.rs"));
+        assert!(!matches("\"$P\"", "lib.rs"));
+        assert!(matches("[", "["));
+    }
+
+    #[test]
+    fn test_abbreviate_home() {
+        let home = Path::new("/home/user");
+        let abbreviate = |path: &str| abbreviate_home(Path::new(path), home);
+        assert_eq!(abbreviate("/home/user"), "~");
+        assert_eq!(abbreviate("/home/user/src"), "~/src");
+        assert_eq!(
+            abbreviate("/home/user/workspace/home/user"),
+            "~/workspace/home/user"
+        );
+        assert_eq!(abbreviate("/x/home/user"), "/x/home/user");
+        assert_eq!(abbreviate("/home/user2"), "/home/user2");
+        assert_eq!(abbreviate_home(Path::new("/usr"), Path::new("/")), "/usr");
+    }
+}
@@ -0,0 +1,146 @@
+// `!!`や`!n`のような履歴の参照を展開する. 展開するものが無ければNoneを返す
+// historyの1番目の要素が`!1`に対応する
+// シングルクォートの中と`\!`は展開しない. `!`の後ろが空白や`=`の場合もただの文字として扱う
+pub fn expand_history(line: &str, history: &[String]) -> Result<Option<String>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::new();
+    let mut expanded = false;
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '\\' if !in_single => {
+                result.push(c);
+                if let Some(&next) = chars.get(i + 1) {
+                    result.push(next);
+                }
+                i += 2;
+                continue;
+            }
+            '!' if !in_single => {
+                if let Some((event, len)) = parse_event(&chars[i + 1..]) {
+                    let spec: String = chars[i..=i + len].iter().collect();
+                    let entry = find_event(&event, history)
+                        .ok_or_else(|| format!("{}: event not found", spec))?;
+                    result.push_str(&entry);
+                    expanded = true;
+                    i += len + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        result.push(c);
+        i += 1;
+    }
+
+    Ok(expanded.then_some(result))
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    // !!
+    Last,
+    // !$. 直前のコマンドの最後の単語
+    LastWord,
+    // !n
+    Number(usize),
+    // !-n. n個前のコマンド
+    Relative(usize),
+    // !prefix. prefixで始まる直近のコマンド
+    Prefix(String),
+}
+
+// `!`の後ろを読んで, 参照するイベントと読んだ文字数を返す
+fn parse_event(chars: &[char]) -> Option<(Event, usize)> {
+    let digits = |chars: &[char]| chars.iter().take_while(|c| c.is_ascii_digit()).count();
+    match chars.first()? {
+        '!' => Some((Event::Last, 1)),
+        '$' => Some((Event::LastWord, 1)),
+        c if c.is_ascii_digit() => {
+            let len = digits(chars);
+            let n = chars[..len].iter().collect::<String>().parse().ok()?;
+            Some((Event::Number(n), len))
+        }
+        '-' => {
+            let len = digits(&chars[1..]);
+            let n = chars[1..=len].iter().collect::<String>().parse().ok()?;
+            Some((Event::Relative(n), len + 1))
+        }
+        c if c.is_whitespace() || "=(\"".contains(*c) => None,
+        _ => {
+            let len = chars
+                .iter()
+                .take_while(|c| !c.is_whitespace() && !";|&<>()'\"".contains(**c))
+                .count();
+            let prefix: String = chars[..len].iter().collect();
+            Some((Event::Prefix(prefix), len))
+        }
+    }
+}
+
+fn find_event(event: &Event, history: &[String]) -> Option<String> {
+    match event {
+        Event::Last => history.last().cloned(),
+        Event::LastWord => history
+            .last()
+            .and_then(|entry| entry.split_whitespace().last())
+            .map(str::to_string),
+        Event::Number(n) => history.get(n.checked_sub(1)?).cloned(),
+        Event::Relative(n) => history.get(history.len().checked_sub(*n)?).cloned(),
+        Event::Prefix(prefix) => history
+            .iter()
+            .rev()
+            .find(|entry| entry.starts_with(prefix.as_str()))
+            .cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_history() {
+        let history: Vec<String> = ["ls -l", "make test", "echo hello world"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let expand = |line: &str| expand_history(line, &history);
+
+        assert_eq!(expand("!!"), Ok(Some("echo hello world".to_string())));
+        assert_eq!(
+            expand("sudo !! | less"),
+            Ok(Some("sudo echo hello world | less".to_string()))
+        );
+        assert_eq!(
+            expand("!1 && !-2"),
+            Ok(Some("ls -l && make test".to_string()))
+        );
+        assert_eq!(
+            expand("!ma; cat !$"),
+            Ok(Some("make test; cat world".to_string()))
+        );
+        assert_eq!(
+            expand("echo \"!!\""),
+            Ok(Some("echo \"echo hello world\"".to_string()))
+        );
+
+        assert_eq!(expand("echo hi"), Ok(None));
+        assert_eq!(expand("echo '!!' \\!! ! a!= !"), Ok(None));
+        assert_eq!(expand("!9"), Err("!9: event not found".to_string()));
+        assert_eq!(
+            expand("!nothing"),
+            Err("!nothing: event not found".to_string())
+        );
+        assert_eq!(
+            expand_history("!!", &[]),
+            Err("!!: event not found".to_string())
+        );
+    }
+}
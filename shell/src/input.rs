@@ -0,0 +1,250 @@
+use std::{
+    io::{self, BufRead, IsTerminal, Read, Write},
+    path::PathBuf,
+};
+
+use dirs::home_dir;
+use nix::unistd;
+use rustyline::{error::ReadlineError, history::FileHistory, CompletionType, Config, Editor};
+
+use crate::{
+    completion::ShellHelper, error::describe, signal::take_interrupted, state::ShellState,
+};
+
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+// コマンドの入力元
+pub enum Input {
+    // 端末から読む場合はrustylineでカーソル移動や履歴の呼び出し, Ctrl-Rでの検索をできるようにする
+    Editor {
+        editor: Box<Editor<ShellHelper, FileHistory>>,
+        history_file: Option<PathBuf>,
+    },
+    // スクリプトファイルや-cの文字列, パイプから読む場合は行編集も履歴も使わない
+    // 行編集を使えない端末から読む場合だけプロンプトを表示する
+    Reader {
+        reader: Box<dyn BufRead>,
+        show_prompt: bool,
+    },
+}
+
+impl Input {
+    // 履歴ファイルから読み込んだ履歴はstate.historyにも入れておき, historyビルトインや`!n`から参照できるようにする
+    pub fn new(state: &mut ShellState) -> Input {
+        if !io::stdin().is_terminal() {
+            return Input::from_reader(Box::new(StdinReader::default()));
+        }
+
+        let history_size = state
+            .var("HISTSIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY_SIZE);
+        let config = Config::builder()
+            .max_history_size(history_size)
+            .and_then(|builder| builder.history_ignore_dups(true))
+            .map(|builder| {
+                builder
+                    .history_ignore_space(true)
+                    .completion_type(CompletionType::List)
+                    .build()
+            });
+        let editor = config.and_then(Editor::with_config);
+        let mut editor = match editor {
+            Ok(editor) => editor,
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                return Input::Reader {
+                    reader: Box::new(StdinReader::default()),
+                    show_prompt: true,
+                };
+            }
+        };
+
+        let history_file = state
+            .var("HISTFILE")
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".shell_history")));
+        editor.set_helper(Some(ShellHelper::default()));
+        if let Some(path) = &history_file {
+            // まだ履歴ファイルが無い場合もあるのでエラーは無視する
+            let _ = editor.load_history(path);
+        }
+        state.history = editor.history().iter().cloned().collect();
+
+        Input::Editor {
+            editor: Box::new(editor),
+            history_file,
+        }
+    }
+
+    pub fn from_reader(reader: Box<dyn BufRead>) -> Input {
+        Input::Reader {
+            reader,
+            show_prompt: false,
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        match self {
+            Input::Editor { .. } => true,
+            Input::Reader { show_prompt, .. } => *show_prompt,
+        }
+    }
+
+    // プロンプトを表示して1行読み, 改行を付けてbufに追加する. 読んだバイト数を返し, EOF(Ctrl-D)の場合は0を返す
+    // Ctrl-Cが押された場合はErrorKind::Interruptedのエラーを返す
+    pub fn read_line(
+        &mut self,
+        state: &ShellState,
+        prompt: &str,
+        buf: &mut String,
+    ) -> io::Result<usize> {
+        match self {
+            Input::Editor { editor, .. } => {
+                if let Some(helper) = editor.helper_mut() {
+                    helper.update(state);
+                }
+                self.read_editor_line(prompt, buf)
+            }
+            Input::Reader {
+                reader,
+                show_prompt,
+            } => {
+                if *show_prompt {
+                    print!("{}", prompt);
+                    io::stdout().flush()?;
+                }
+                read_line_from(reader.as_mut(), buf)
+            }
+        }
+    }
+
+    fn read_editor_line(&mut self, prompt: &str, buf: &mut String) -> io::Result<usize> {
+        let Input::Editor { editor, .. } = self else {
+            return Ok(0);
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buf.push_str(&line);
+                buf.push('\n');
+                Ok(line.len() + 1)
+            }
+            Err(ReadlineError::Interrupted) => Err(io::ErrorKind::Interrupted.into()),
+            Err(ReadlineError::Eof) => Ok(0),
+            Err(ReadlineError::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+
+    // 入力されたコマンドを履歴に追加して履歴ファイルにも書き込む
+    pub fn add_history(&mut self, state: &mut ShellState, entry: &str) {
+        if entry.trim().is_empty() {
+            return;
+        }
+        self.sync_history(state);
+        let Input::Editor {
+            editor,
+            history_file,
+        } = self
+        else {
+            return;
+        };
+        if let Ok(true) = editor.add_history_entry(entry) {
+            if let Some(path) = history_file {
+                match editor.append_history(path) {
+                    Ok(()) => (),
+                    Err(ReadlineError::Io(e)) => {
+                        eprintln!("shell: {}: {}", path.display(), describe(&e))
+                    }
+                    Err(e) => eprintln!("shell: {}: {}", path.display(), e),
+                }
+            }
+        }
+        state.history = editor.history().iter().cloned().collect();
+    }
+
+    // `history -c`などでstate.historyが書き換えられていれば, 行編集で呼び出せる履歴もそれに合わせる
+    fn sync_history(&mut self, state: &ShellState) {
+        let Input::Editor { editor, .. } = self else {
+            return;
+        };
+        if editor.history().iter().eq(state.history.iter()) {
+            return;
+        }
+        let _ = editor.clear_history();
+        for entry in &state.history {
+            let _ = editor.add_history_entry(entry.as_str());
+        }
+    }
+}
+
+// 標準入力(fd 0)から1バイトずつ読む. io::stdin()のようにまとめて先読みすると, パイプで渡されたスクリプトの
+// 続きに書かれていて, スクリプトの中のreadや子プロセスが読むはずの入力までシェルが奪ってしまう
+#[derive(Default)]
+struct StdinReader {
+    byte: [u8; 1],
+    // byteに読んだまままだ消費されていないバイト数. 0か1
+    len: usize,
+}
+
+impl Read for StdinReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for StdinReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.len == 0 {
+            self.len = unistd::read(0, &mut self.byte)?;
+        }
+        Ok(&self.byte[..self.len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.len -= amt.min(self.len);
+    }
+}
+
+// 1行読んでbufに追加する. 読んだバイト数を返し, EOFの場合は0を返す
+// std::io::BufRead::read_lineはEINTRを無視して読み続けてしまうので, Ctrl-Cで入力を中断できるように自前で読む
+// Ctrl-Cが押された場合はErrorKind::Interruptedのエラーを返す
+fn read_line_from(reader: &mut dyn BufRead, buf: &mut String) -> io::Result<usize> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if take_interrupted() {
+                    return Err(e);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            break;
+        }
+
+        match available.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                bytes.extend_from_slice(&available[..=i]);
+                reader.consume(i + 1);
+                break;
+            }
+            None => {
+                let len = available.len();
+                bytes.extend_from_slice(available);
+                reader.consume(len);
+            }
+        }
+    }
+
+    buf.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(bytes.len())
+}
@@ -0,0 +1,448 @@
+use std::{
+    io::{self, IsTerminal},
+    os::fd::{AsFd, AsRawFd, OwnedFd, RawFd},
+};
+
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{kill, killpg, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{getpgrp, setpgid, tcgetpgrp, tcsetpgrp, Pid},
+};
+
+use crate::signal::init_interactive_signals;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessState {
+    Running,
+    Stopped,
+    // 終了ステータス. シグナルで終了した場合は128+シグナル番号
+    Done(i32),
+}
+
+// パイプラインを構成する各コマンド
+#[derive(Debug)]
+pub struct Process {
+    // ビルトインや起動に失敗したコマンドのように子プロセスが無い場合はNone
+    pub pid: Option<Pid>,
+    pub name: String,
+    pub state: ProcessState,
+}
+
+impl Process {
+    pub fn spawned(pid: Pid, name: &str) -> Self {
+        Process {
+            pid: Some(pid),
+            name: name.to_string(),
+            state: ProcessState::Running,
+        }
+    }
+
+    pub fn finished(name: &str, status: i32) -> Self {
+        Process {
+            pid: None,
+            name: name.to_string(),
+            state: ProcessState::Done(status),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.state == ProcessState::Running
+    }
+
+    fn update(&mut self, wait_status: WaitStatus) {
+        match wait_status {
+            WaitStatus::Exited(_, code) => self.state = ProcessState::Done(code),
+            WaitStatus::Signaled(_, signal, core_dumped) => {
+                report_signal(&self.name, signal, core_dumped);
+                self.state = ProcessState::Done(128 + signal as i32);
+            }
+            WaitStatus::Stopped(..) => self.state = ProcessState::Stopped,
+            WaitStatus::Continued(_) => self.state = ProcessState::Running,
+            _ => (),
+        }
+    }
+
+    fn wait(&mut self, flags: WaitPidFlag) {
+        let Some(pid) = self.pid else {
+            return;
+        };
+        loop {
+            match waitpid(pid, Some(flags)) {
+                Ok(wait_status) => self.update(wait_status),
+                Err(Errno::EINTR) => continue,
+                // 既に回収済みなど, もう待つことができない場合は終了したものとして扱う
+                Err(_) => self.state = ProcessState::Done(0),
+            }
+            return;
+        }
+    }
+}
+
+// シグナルで終了したコマンドがあれば知らせる. パイプの途中のコマンドが落ちても最後の終了ステータスからは分からないため
+// ただしSIGPIPE(`yes | head`のように読み手が先に終了すると普通に起きる)とSIGINT(Ctrl-C)は表示しない
+fn report_signal(name: &str, signal: Signal, core_dumped: bool) {
+    if signal != Signal::SIGINT && signal != Signal::SIGPIPE {
+        let core = if core_dumped { " (core dumped)" } else { "" };
+        eprintln!("shell: {}: terminated by {}{}", name, signal, core);
+    }
+}
+
+// 1つのパイプライン(もしくはバックグラウンドで実行されたコマンドリスト)が1つのジョブになる
+#[derive(Debug)]
+pub struct Job {
+    // ジョブテーブルに登録されるまでは0
+    pub id: usize,
+    // ジョブ制御が無効な場合はプロセスグループを作らないのでNone
+    pub pgid: Option<Pid>,
+    pub processes: Vec<Process>,
+    pub command: String,
+}
+
+impl Job {
+    pub fn new(command: &str, pgid: Option<Pid>, processes: Vec<Process>) -> Self {
+        Job {
+            id: 0,
+            pgid,
+            processes,
+            command: command.to_string(),
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.processes
+            .iter()
+            .any(|process| process.state == ProcessState::Stopped)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.processes
+            .iter()
+            .all(|process| matches!(process.state, ProcessState::Done(_)))
+    }
+
+    // 各コマンドの終了ステータス. 停止中のコマンドは128+SIGTSTPとする
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|process| match process.state {
+                ProcessState::Done(status) => status,
+                _ => 128 + Signal::SIGTSTP as i32,
+            })
+            .collect()
+    }
+
+    pub fn pids(&self) -> Vec<Pid> {
+        self.processes.iter().filter_map(|p| p.pid).collect()
+    }
+
+    // 全てのコマンドが終了するか, どれかが停止するまで待つ
+    pub fn wait(&mut self) {
+        for process in &mut self.processes {
+            while process.is_running() {
+                process.wait(WaitPidFlag::WUNTRACED);
+            }
+            if process.state == ProcessState::Stopped {
+                return;
+            }
+        }
+    }
+
+    // 待たずに状態だけ更新する
+    pub fn poll(&mut self) {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        for process in &mut self.processes {
+            if !matches!(process.state, ProcessState::Done(_)) {
+                process.wait(flags);
+            }
+        }
+    }
+
+    // 停止中のジョブにSIGCONTを送って再開させる
+    pub fn resume(&mut self) -> nix::Result<()> {
+        self.signal(Signal::SIGCONT)?;
+        for process in &mut self.processes {
+            if process.state == ProcessState::Stopped {
+                process.state = ProcessState::Running;
+            }
+        }
+        Ok(())
+    }
+
+    // プロセスグループがあればグループ全体に, 無ければ各プロセスにシグナルを送る
+    pub fn signal(&self, signal: Signal) -> nix::Result<()> {
+        match self.pgid {
+            Some(pgid) => killpg(pgid, signal),
+            None => self
+                .pids()
+                .into_iter()
+                .try_for_each(|pid| kill(pid, signal)),
+        }
+    }
+
+    fn state_label(&self) -> String {
+        if self.is_stopped() {
+            return "Stopped".to_string();
+        }
+        if !self.is_done() {
+            return "Running".to_string();
+        }
+        match self.statuses().last().copied().unwrap_or(0) {
+            0 => "Done".to_string(),
+            status if status > 128 => match Signal::try_from(status - 128) {
+                Ok(Signal::SIGTERM) => "Terminated".to_string(),
+                Ok(Signal::SIGKILL) => "Killed".to_string(),
+                Ok(Signal::SIGINT) => "Interrupt".to_string(),
+                Ok(signal) => signal.as_str().trim_start_matches("SIG").to_string(),
+                Err(_) => format!("Exit {}", status),
+            },
+            status => format!("Exit {}", status),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobTable {
+    // 追加された順に並んでいる. 最後の要素がカレントジョブ(+), その1つ前が直前のジョブ(-)
+    jobs: Vec<Job>,
+}
+
+impl JobTable {
+    pub fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        self.jobs.push(job);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    // id順に並べたジョブのid一覧
+    pub fn ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.jobs.iter().map(|job| job.id).collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn current(&self) -> Option<usize> {
+        self.jobs.last().map(|job| job.id)
+    }
+
+    pub fn previous(&self) -> Option<usize> {
+        self.jobs.iter().rev().nth(1).map(|job| job.id)
+    }
+
+    // %1, %%, %+, %-, %文字列 (コマンドの先頭が一致するジョブ) の形式でジョブを指定する
+    pub fn resolve(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let Some(spec_body) = spec.strip_prefix('%') else {
+            return Err(no_such_job());
+        };
+        match spec_body {
+            "" | "%" | "+" => self.current().ok_or_else(no_such_job),
+            "-" => self.previous().ok_or_else(no_such_job),
+            _ => match spec_body.parse::<usize>() {
+                Ok(id) => self.get(id).map(|job| job.id).ok_or_else(no_such_job),
+                Err(_) => {
+                    let mut matched = self
+                        .jobs
+                        .iter()
+                        .filter(|job| job.command.starts_with(spec_body));
+                    match (matched.next(), matched.next()) {
+                        (Some(job), None) => Ok(job.id),
+                        (Some(_), Some(_)) => Err(format!("{}: ambiguous job spec", spec)),
+                        _ => Err(no_such_job()),
+                    }
+                }
+            },
+        }
+    }
+
+    // バックグラウンドのジョブの状態を待たずに更新する
+    pub fn poll(&mut self) {
+        for job in &mut self.jobs {
+            job.poll();
+        }
+    }
+
+    // 終了したジョブをテーブルから取り除いて返す
+    pub fn take_finished(&mut self) -> Vec<Job> {
+        let (finished, jobs) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| job.is_done());
+        self.jobs = jobs;
+        finished
+    }
+
+    // バックグラウンドのジョブの状態を更新し, 終了したものを`[1]+  Done    sleep 1`のような通知と共に取り除く
+    pub fn reap(&mut self) -> Vec<String> {
+        self.poll();
+        let notifications = self
+            .jobs
+            .iter()
+            .filter(|job| job.is_done())
+            .map(|job| self.format(job))
+            .collect();
+        self.take_finished();
+        notifications
+    }
+
+    fn marker(&self, id: usize) -> char {
+        if Some(id) == self.current() {
+            '+'
+        } else if Some(id) == self.previous() {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    // `[1]+  Running                 sleep 10 &`のようにbashと同じ形式で表示する
+    pub fn format(&self, job: &Job) -> String {
+        let background = if job.is_stopped() || job.is_done() {
+            ""
+        } else {
+            " &"
+        };
+        format!(
+            "[{}]{}  {:<24}{}{}",
+            job.id,
+            self.marker(job.id),
+            job.state_label(),
+            job.command,
+            background
+        )
+    }
+}
+
+// ジョブ制御で使う制御端末. フォアグラウンドのジョブに端末を渡したり, シェルに取り戻したりする
+#[derive(Debug)]
+pub struct Terminal {
+    fd: OwnedFd,
+    shell_pgid: Pid,
+}
+
+impl Terminal {
+    pub fn give_to(&self, pgid: Pid) {
+        let _ = tcsetpgrp(&self.fd, pgid);
+    }
+
+    pub fn take_back(&self) {
+        let _ = tcsetpgrp(&self.fd, self.shell_pgid);
+    }
+
+    // 子プロセスがexec前に端末を受け取るために使う. execする時に閉じられる
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+// 標準入力が端末の場合はジョブ制御を有効にする. シェルを独自のプロセスグループにしてフォアグラウンドにする
+pub fn init_job_control() -> Option<Terminal> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return None;
+    }
+
+    // バックグラウンドで起動された場合はフォアグラウンドになるまで自分を止めて待つ
+    loop {
+        let pgrp = getpgrp();
+        match tcgetpgrp(stdin.as_fd()) {
+            Ok(foreground) if foreground == pgrp => break,
+            Ok(_) => {
+                let _ = kill(Pid::from_raw(-pgrp.as_raw()), Signal::SIGTTIN);
+            }
+            Err(_) => return None,
+        }
+    }
+
+    init_interactive_signals();
+
+    // セッションリーダーとして起動された場合などは失敗するが, その場合は既に自分のグループにいる
+    let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+    let shell_pgid = getpgrp();
+    let fd = stdin.as_fd().try_clone_to_owned().ok()?;
+    let terminal = Terminal { fd, shell_pgid };
+    terminal.take_back();
+    Some(terminal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(command: &str, state: ProcessState) -> Job {
+        Job::new(
+            command,
+            None,
+            vec![Process {
+                pid: None,
+                name: command.to_string(),
+                state,
+            }],
+        )
+    }
+
+    #[test]
+    fn test_resolve_job_spec() {
+        let mut jobs = JobTable::default();
+        jobs.add(job("sleep 100", ProcessState::Running));
+        jobs.add(job("vim foo.rs", ProcessState::Stopped));
+        jobs.add(job("sleep 200", ProcessState::Running));
+
+        assert_eq!(jobs.resolve("%1"), Ok(1));
+        assert_eq!(jobs.resolve("%%"), Ok(3));
+        assert_eq!(jobs.resolve("%+"), Ok(3));
+        assert_eq!(jobs.resolve("%-"), Ok(2));
+        assert_eq!(jobs.resolve("%vim"), Ok(2));
+        assert!(jobs.resolve("%sleep").is_err());
+        assert!(jobs.resolve("%4").is_err());
+        assert!(jobs.resolve("1").is_err());
+
+        // 削除された番号は再利用せず, 最大の番号+1を割り当てる
+        jobs.remove(2);
+        assert_eq!(jobs.add(job("make", ProcessState::Running)), 4);
+        assert_eq!(jobs.ids(), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn test_format_job() {
+        let mut jobs = JobTable::default();
+        jobs.add(job("sleep 100", ProcessState::Running));
+        jobs.add(job("vim", ProcessState::Stopped));
+        jobs.add(job("false", ProcessState::Done(1)));
+
+        let lines: Vec<String> = jobs
+            .ids()
+            .into_iter()
+            .filter_map(|id| jobs.get(id))
+            .map(|job| jobs.format(job))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[1]   Running                 sleep 100 &",
+                "[2]-  Stopped                 vim",
+                "[3]+  Exit 1                  false",
+            ]
+        );
+
+        assert_eq!(jobs.reap(), vec!["[3]+  Exit 1                  false"]);
+        assert_eq!(jobs.ids(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,1024 @@
+use std::fmt::Display;
+
+use crate::token::{ParamExpansion, ParamOp, RedirectOp, Token, Word, WordPart};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+    UnterminatedQuote(char),
+    TrailingBackslash,
+    // ${ に対応する } が無い
+    UnterminatedBrace,
+    // ${x y} のように ${ } の中身が不正
+    BadSubstitution,
+    // $( や $(( に対応する ) が無い
+    UnterminatedParen,
+    // ヒアドキュメントの区切り文字だけの行が見つからない
+    UnterminatedHereDoc(String),
+}
+
+impl LexError {
+    // 次の行を読めば続きが得られるエラーかどうか
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            LexError::UnterminatedQuote(_)
+            | LexError::TrailingBackslash
+            | LexError::UnterminatedBrace
+            | LexError::UnterminatedParen
+            | LexError::UnterminatedHereDoc(_) => true,
+            LexError::BadSubstitution => false,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::UnterminatedQuote(c) => {
+                write!(f, "unexpected EOF while looking for matching `{}'", c)
+            }
+            LexError::TrailingBackslash => write!(f, "unexpected EOF after backslash"),
+            LexError::UnterminatedBrace => {
+                write!(f, "unexpected EOF while looking for matching `}}'")
+            }
+            LexError::BadSubstitution => write!(f, "bad substitution"),
+            LexError::UnterminatedParen => {
+                write!(f, "unexpected EOF while looking for matching `)'")
+            }
+            LexError::UnterminatedHereDoc(delimiter) => write!(
+                f,
+                "here-document delimited by end-of-file (wanted `{}')",
+                delimiter
+            ),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::new(input).tokenize()
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    // 本文をまだ読んでいないヒアドキュメント. 次の改行の後ろから読む
+    here_docs: Vec<PendingHereDoc>,
+}
+
+struct PendingHereDoc {
+    // 区切り文字の単語のtokensの中での位置
+    index: usize,
+    strip_tabs: bool,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Lexer {
+            chars: input.chars().collect(),
+            pos: 0,
+            tokens: Vec::new(),
+            here_docs: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, LexError> {
+        while self.peek().is_some() {
+            self.read_token()?;
+        }
+        // 本文を読む前に入力が終わった. <<の後ろに単語が無い場合はパースエラーにする
+        for pending in &self.here_docs {
+            if let Some(Token::Word(word)) = self.tokens.get(pending.index) {
+                return Err(LexError::UnterminatedHereDoc(word.unquote()));
+            }
+        }
+        Ok(self.tokens)
+    }
+
+    // 1つのトークンを読んでtokensに追加する. 空白やコメントの場合は読み飛ばすだけ
+    fn read_token(&mut self) -> Result<(), LexError> {
+        let Some(c) = self.peek() else {
+            return Ok(());
+        };
+        match c {
+            ' ' | '\t' => {
+                self.next();
+            }
+            '\n' => {
+                self.next();
+                self.tokens.push(Token::Newline);
+                self.read_here_doc_bodies()?;
+            }
+            // 単語の外のバックスラッシュ+改行は行の継続なので両方とも捨てる
+            '\\' if self.peek_nth(1) == Some('\n') => {
+                self.pos += 2;
+                if self.peek().is_none() {
+                    return Err(LexError::TrailingBackslash);
+                }
+            }
+            '#' => self.skip_comment(),
+            '|' => {
+                let token = if self.eat("||") {
+                    Token::OrIf
+                } else {
+                    self.next();
+                    Token::Pipe
+                };
+                self.tokens.push(token);
+            }
+            '&' if self.eat("&&") => self.tokens.push(Token::AndIf),
+            '&' if self.peek_nth(1) != Some('>') => {
+                self.next();
+                self.tokens.push(Token::Amp);
+            }
+            ';' => {
+                let token = if self.eat(";;") {
+                    Token::DSemi
+                } else {
+                    self.next();
+                    Token::Semi
+                };
+                self.tokens.push(token);
+            }
+            '(' => {
+                self.next();
+                self.tokens.push(Token::LParen);
+            }
+            ')' => {
+                self.next();
+                self.tokens.push(Token::RParen);
+            }
+            '<' | '>' => {
+                let op = self.read_redirect_op();
+                self.push_redirect(None, op);
+            }
+            '&' if self.peek_nth(1) == Some('>') => {
+                let op = self.read_redirect_op();
+                self.tokens.push(Token::Redirect(None, op));
+            }
+            // `2>file`のように数字の直後にリダイレクト演算子が続く場合, その数字はfd番号として扱う
+            '0'..='9' if self.io_number_len().is_some() => {
+                let len = self.io_number_len().unwrap_or_default();
+                let fd: String = self.chars[self.pos..self.pos + len].iter().collect();
+                self.pos += len;
+                let op = self.read_redirect_op();
+                self.push_redirect(fd.parse().ok(), op);
+            }
+            _ => {
+                let word = self.read_word()?;
+                self.tokens.push(Token::Word(word));
+            }
+        }
+        Ok(())
+    }
+
+    // <<と<<-の場合は, 次に読む区切り文字の単語の本文を次の改行の後ろから読めるように覚えておく
+    fn push_redirect(&mut self, fd: Option<u32>, op: RedirectOp) {
+        self.tokens.push(Token::Redirect(fd, op));
+        if matches!(op, RedirectOp::DLess | RedirectOp::DLessDash) {
+            self.here_docs.push(PendingHereDoc {
+                index: self.tokens.len(),
+                strip_tabs: op == RedirectOp::DLessDash,
+            });
+        }
+    }
+
+    // 改行の後ろから, 溜まっているヒアドキュメントの本文を順番に読む
+    // 区切り文字の単語をクォートした場合は本文をそのまま使い, そうでなければ$や`を展開する
+    fn read_here_doc_bodies(&mut self) -> Result<(), LexError> {
+        for pending in std::mem::take(&mut self.here_docs) {
+            let Some(Token::Word(word)) = self.tokens.get(pending.index) else {
+                continue;
+            };
+            let delimiter = word.clone();
+            let text = delimiter.unquote();
+            let quoted = delimiter
+                .parts
+                .iter()
+                .any(|part| matches!(part, WordPart::Quoted(_) | WordPart::DoubleQuoted(_)));
+
+            let mut body = String::new();
+            loop {
+                if self.peek().is_none() {
+                    return Err(LexError::UnterminatedHereDoc(text));
+                }
+                let mut line = String::new();
+                while let Some(c) = self.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                let line = if pending.strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == text {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+
+            let body = if quoted {
+                Word::new(vec![WordPart::Quoted(body)])
+            } else {
+                // 本文はすでに区切られているので, 閉じていない${などは続きを読んでも直らない
+                let parts = Lexer::new(&body).read_here_doc_text().map_err(|e| {
+                    if e.is_incomplete() {
+                        LexError::BadSubstitution
+                    } else {
+                        e
+                    }
+                })?;
+                // ダブルクォートの中と同じく, ~の展開や単語分割はしない
+                Word::new(vec![WordPart::DoubleQuoted(parts)])
+            };
+            self.tokens[pending.index] = Token::HereDoc { delimiter, body };
+        }
+        Ok(())
+    }
+
+    // ヒアドキュメントの本文を読む. ダブルクォートの中とほぼ同じだが, "は普通の文字として扱う
+    fn read_here_doc_text(&mut self) -> Result<Vec<WordPart>, LexError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        loop {
+            if let Some(part) = self.read_dollar()? {
+                flush_literal(&mut literal, &mut parts);
+                parts.push(part);
+                continue;
+            }
+            match self.next() {
+                Some('`') => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::CommandSubst(self.read_backquoted()?));
+                }
+                Some('\\') => match self.peek() {
+                    Some('\n') => {
+                        self.next();
+                    }
+                    Some(c) if matches!(c, '$' | '`' | '\\') => {
+                        self.next();
+                        literal.push(c);
+                    }
+                    _ => literal.push('\\'),
+                },
+                Some(c) => literal.push(c),
+                None => {
+                    flush_literal(&mut literal, &mut parts);
+                    return Ok(parts);
+                }
+            }
+        }
+    }
+
+    fn io_number_len(&self) -> Option<usize> {
+        let len = self.chars[self.pos..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        match self.peek_nth(len) {
+            Some('<' | '>') => Some(len),
+            _ => None,
+        }
+    }
+
+    // 入力の先頭がsに一致すれば読み進めてtrueを返す
+    fn eat(&mut self, s: &str) -> bool {
+        let matched = s
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_nth(i) == Some(c));
+        if matched {
+            self.pos += s.chars().count();
+        }
+        matched
+    }
+
+    fn read_redirect_op(&mut self) -> RedirectOp {
+        // 長いものから順に試す
+        if self.eat("&>>") {
+            RedirectOp::AndDGreat
+        } else if self.eat("&>") {
+            RedirectOp::AndGreat
+        } else if self.eat(">>") {
+            RedirectOp::DGreat
+        } else if self.eat(">&") {
+            RedirectOp::GreatAnd
+        } else if self.eat("<<<") {
+            RedirectOp::TLess
+        } else if self.eat("<<-") {
+            RedirectOp::DLessDash
+        } else if self.eat("<<") {
+            RedirectOp::DLess
+        } else if self.eat("<&") {
+            RedirectOp::LessAnd
+        } else if self.eat(">") {
+            RedirectOp::Great
+        } else {
+            self.eat("<");
+            RedirectOp::Less
+        }
+    }
+
+    // $から始まるパラメータを読む. `$ `や`$/`のように後ろが変数名でない場合はただの文字として扱うのでNoneを返す
+    fn read_dollar(&mut self) -> Result<Option<WordPart>, LexError> {
+        if self.peek() != Some('$') {
+            return Ok(None);
+        }
+        let param = match self.peek_nth(1) {
+            Some('(') if self.peek_nth(2) == Some('(') => {
+                self.pos += 3;
+                return Ok(Some(WordPart::Arith(self.read_arith()?)));
+            }
+            Some('(') => {
+                self.pos += 2;
+                return Ok(Some(WordPart::CommandSubst(self.read_command_subst()?)));
+            }
+            Some('{') => {
+                self.pos += 2;
+                self.read_braced_param()?
+            }
+            Some(c) if is_special_param(c) || c.is_ascii_digit() => {
+                // $10は${1}0と解釈される. 2桁以上の位置パラメータは${10}と書く必要がある
+                self.pos += 2;
+                ParamExpansion::plain(&c.to_string())
+            }
+            Some(c) if is_name_start(c) => {
+                self.next();
+                ParamExpansion::plain(&self.read_name())
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(WordPart::Param(param)))
+    }
+
+    // `$(`の後ろから対応する`)`までを読み, 中身をそのまま返す
+    // 中にクォートや括弧が含まれていても対応が取れるように, 中身も普段と同じようにトークンに区切りながら読む
+    fn read_command_subst(&mut self) -> Result<String, LexError> {
+        let outer = std::mem::take(&mut self.tokens);
+        let outer_here_docs = std::mem::take(&mut self.here_docs);
+        let start = self.pos;
+        let end = self.find_closing_paren();
+        self.tokens = outer;
+        self.here_docs = outer_here_docs;
+        let end = end?;
+        Ok(self.chars[start..end].iter().collect())
+    }
+
+    // 対応する`)`を読み進めて, その位置を返す. `case x in a) ...`のパターンの後ろの`)`は数えない
+    fn find_closing_paren(&mut self) -> Result<usize, LexError> {
+        let mut depth = 0;
+        let mut cases = 0;
+        while self.peek().is_some() {
+            let pos = self.pos;
+            let len = self.tokens.len();
+            self.read_token()?;
+            match self.tokens.get(len) {
+                Some(Token::LParen) => depth += 1,
+                Some(Token::RParen) if depth > 0 => depth -= 1,
+                Some(Token::RParen) if cases == 0 => return Ok(pos),
+                Some(Token::Word(word)) => match word.as_literal() {
+                    Some("case") => cases += 1,
+                    Some("esac") if cases > 0 => cases -= 1,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Err(LexError::UnterminatedParen)
+    }
+
+    // `$((`の後ろから対応する`))`までを読む. 中の$xや$(...)は式を評価する前に展開するのでパーツとして持つ
+    fn read_arith(&mut self) -> Result<Vec<WordPart>, LexError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut depth = 0;
+        loop {
+            if let Some(part) = self.read_dollar()? {
+                flush_literal(&mut literal, &mut parts);
+                parts.push(part);
+                continue;
+            }
+            match self.next() {
+                Some(')') if depth == 0 && self.peek() == Some(')') => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    return Ok(parts);
+                }
+                Some('(') => {
+                    depth += 1;
+                    literal.push('(');
+                }
+                Some(')') if depth > 0 => {
+                    depth -= 1;
+                    literal.push(')');
+                }
+                Some(')') => return Err(LexError::BadSubstitution),
+                Some('"') => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                }
+                Some('`') => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::CommandSubst(self.read_backquoted()?));
+                }
+                Some('\\') => match self.next() {
+                    Some('\n') => {}
+                    Some(c) => literal.push(c),
+                    None => return Err(LexError::TrailingBackslash),
+                },
+                Some(c) => literal.push(c),
+                None => return Err(LexError::UnterminatedParen),
+            }
+        }
+    }
+
+    // `...`の中身を読む. 中では\$, \`, \\だけがエスケープとして扱われ, それ以外の\はそのまま残す
+    fn read_backquoted(&mut self) -> Result<String, LexError> {
+        let mut source = String::new();
+        loop {
+            match self.next() {
+                Some('`') => return Ok(source),
+                Some('\\') => match self.peek() {
+                    Some(c) if matches!(c, '$' | '`' | '\\') => {
+                        self.next();
+                        source.push(c);
+                    }
+                    _ => source.push('\\'),
+                },
+                Some(c) => source.push(c),
+                None => return Err(LexError::UnterminatedQuote('`')),
+            }
+        }
+    }
+
+    fn read_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if !(c == '_' || c.is_ascii_alphanumeric()) {
+                break;
+            }
+            self.next();
+            name.push(c);
+        }
+        name
+    }
+
+    // ${ の後ろを読む
+    fn read_braced_param(&mut self) -> Result<ParamExpansion, LexError> {
+        // ${#}は位置パラメータの個数なので, #の後ろに名前が続く場合だけ${#name}として扱う
+        let length = self.peek() == Some('#') && !matches!(self.peek_nth(1), Some('}') | None);
+        if length {
+            self.next();
+        }
+
+        let name = match self.peek() {
+            Some(c) if is_special_param(c) => {
+                self.next();
+                c.to_string()
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut name = String::new();
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+                    self.next();
+                    name.push(c);
+                }
+                name
+            }
+            Some(c) if is_name_start(c) => self.read_name(),
+            Some(_) => return Err(LexError::BadSubstitution),
+            None => return Err(LexError::UnterminatedBrace),
+        };
+
+        let op = match self.next() {
+            Some('}') if length => ParamOp::Length,
+            Some('}') => ParamOp::Plain,
+            _ if length => return Err(LexError::BadSubstitution),
+            Some(':') => match self.next() {
+                Some(op) => self.read_param_op(op, true)?,
+                None => return Err(LexError::UnterminatedBrace),
+            },
+            Some(op) => self.read_param_op(op, false)?,
+            None => return Err(LexError::UnterminatedBrace),
+        };
+        Ok(ParamExpansion { name, op })
+    }
+
+    fn read_param_op(&mut self, op: char, colon: bool) -> Result<ParamOp, LexError> {
+        let make_op = match op {
+            '-' => ParamOp::Default,
+            '=' => ParamOp::Assign,
+            '+' => ParamOp::Alternative,
+            '?' => ParamOp::Error,
+            _ => return Err(LexError::BadSubstitution),
+        };
+        Ok(make_op(colon, self.read_param_word()?))
+    }
+
+    // ${name:-word}のwordの部分を } まで読む. wordの中にもクォートやパラメータを書ける
+    fn read_param_word(&mut self) -> Result<Word, LexError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        loop {
+            match self.peek() {
+                Some('}') => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    return Ok(Word::new(parts));
+                }
+                Some('\'') => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::Quoted(self.read_single_quoted()?));
+                }
+                Some('"') => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                }
+                Some('$') => match self.read_dollar()? {
+                    Some(part) => {
+                        flush_literal(&mut literal, &mut parts);
+                        parts.push(part);
+                    }
+                    None => {
+                        self.next();
+                        literal.push('$');
+                    }
+                },
+                Some('\\') => {
+                    self.next();
+                    match self.next() {
+                        Some(escaped) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(WordPart::Quoted(escaped.to_string()));
+                        }
+                        None => return Err(LexError::TrailingBackslash),
+                    }
+                }
+                Some(c) => {
+                    self.next();
+                    literal.push(c);
+                }
+                None => return Err(LexError::UnterminatedBrace),
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    // 空白やメタ文字が現れるまでを1つの単語として読む. クォートの内側の空白やメタ文字は単語の一部になる
+    fn read_word(&mut self) -> Result<Word, LexError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' | '|' | ';' | '<' | '>' | '(' | ')' => break,
+                '&' => break,
+                '\'' => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::Quoted(self.read_single_quoted()?));
+                }
+                '"' => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::DoubleQuoted(self.read_double_quoted()?));
+                }
+                '`' => {
+                    self.next();
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::CommandSubst(self.read_backquoted()?));
+                }
+                '$' => match self.read_dollar()? {
+                    Some(part) => {
+                        flush_literal(&mut literal, &mut parts);
+                        parts.push(part);
+                    }
+                    None => {
+                        self.next();
+                        literal.push(c);
+                    }
+                },
+                '\\' => {
+                    self.next();
+                    match self.next() {
+                        Some('\n') => {
+                            if self.peek().is_none() {
+                                return Err(LexError::TrailingBackslash);
+                            }
+                        }
+                        Some(escaped) => {
+                            flush_literal(&mut literal, &mut parts);
+                            parts.push(WordPart::Quoted(escaped.to_string()));
+                        }
+                        None => return Err(LexError::TrailingBackslash),
+                    }
+                }
+                _ => {
+                    self.next();
+                    literal.push(c);
+                }
+            }
+        }
+
+        flush_literal(&mut literal, &mut parts);
+        Ok(Word::new(parts))
+    }
+
+    fn read_single_quoted(&mut self) -> Result<String, LexError> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+                None => return Err(LexError::UnterminatedQuote('\'')),
+            }
+        }
+    }
+
+    // ダブルクォートの中ではバックスラッシュは $ ` " \ 改行 の前でのみ特別な意味を持つ
+    fn read_double_quoted(&mut self) -> Result<Vec<WordPart>, LexError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        loop {
+            if let Some(part) = self.read_dollar()? {
+                flush_literal(&mut literal, &mut parts);
+                parts.push(part);
+                continue;
+            }
+            match self.next() {
+                Some('"') => {
+                    flush_literal(&mut literal, &mut parts);
+                    return Ok(parts);
+                }
+                Some('`') => {
+                    flush_literal(&mut literal, &mut parts);
+                    parts.push(WordPart::CommandSubst(self.read_backquoted()?));
+                }
+                Some('\\') => match self.peek() {
+                    Some('\n') => {
+                        self.next();
+                    }
+                    Some(c) if matches!(c, '$' | '`' | '"' | '\\') => {
+                        self.next();
+                        literal.push(c);
+                    }
+                    _ => literal.push('\\'),
+                },
+                Some(c) => literal.push(c),
+                None => return Err(LexError::UnterminatedQuote('"')),
+            }
+        }
+    }
+}
+
+// $?, $$ のように1文字で特別な意味を持つパラメータ
+fn is_special_param(c: char) -> bool {
+    matches!(c, '?' | '$' | '!' | '#' | '@' | '*' | '-')
+}
+
+fn is_name_start(c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic()
+}
+
+fn flush_literal(literal: &mut String, parts: &mut Vec<WordPart>) {
+    if !literal.is_empty() {
+        parts.push(WordPart::Literal(std::mem::take(literal)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => word.unquote(),
+                token => token.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_pipeline() {
+        assert_eq!(
+            words("cat file.txt | grep  something\n"),
+            vec!["cat", "file.txt", "|", "grep", "something", "newline"]
+        );
+        assert_eq!(words("ls|wc -l"), vec!["ls", "|", "wc", "-l"]);
+    }
+
+    #[test]
+    fn test_tokenize_quotes() {
+        assert_eq!(words(r#"echo "a | b""#), vec!["echo", "a | b"]);
+        assert_eq!(
+            words("grep 'foo bar' file"),
+            vec!["grep", "foo bar", "file"]
+        );
+        assert_eq!(
+            words(r#"echo "it's" 'say "hi"'"#),
+            vec!["echo", "it's", r#"say "hi""#]
+        );
+        assert_eq!(words(r#"echo foo"bar"'baz'"#), vec!["echo", "foobarbaz"]);
+        assert_eq!(words(r#"echo """#), vec!["echo", ""]);
+    }
+
+    #[test]
+    fn test_tokenize_escapes() {
+        assert_eq!(words(r"echo a\ b \| c"), vec!["echo", "a b", "|", "c"]);
+        assert_eq!(words(r#"echo "\"\$x\n""#), vec!["echo", r#""$x\n"#]);
+        assert_eq!(words(r"echo 'a\b'"), vec!["echo", r"a\b"]);
+    }
+
+    #[test]
+    fn test_tokenize_line_continuation() {
+        assert_eq!(words("echo foo \\\nbar"), vec!["echo", "foo", "bar"]);
+        assert_eq!(words("echo \"foo\nbar\""), vec!["echo", "foo\nbar"]);
+    }
+
+    #[test]
+    fn test_tokenize_comment() {
+        assert_eq!(words("echo foo # comment | bar"), vec!["echo", "foo"]);
+        assert_eq!(words("echo foo#bar"), vec!["echo", "foo#bar"]);
+    }
+
+    #[test]
+    fn test_tokenize_list() {
+        assert_eq!(
+            words("make && ./a.out || echo failed; echo $?"),
+            vec!["make", "&&", "./a.out", "||", "echo", "failed", ";", "echo", "$?"]
+        );
+        assert_eq!(
+            words("true&&false;ls"),
+            vec!["true", "&&", "false", ";", "ls"]
+        );
+        assert_eq!(words("echo 'a && b;'"), vec!["echo", "a && b;"]);
+    }
+
+    #[test]
+    fn test_tokenize_case() {
+        assert_eq!(
+            words("case $x in (a|b) ls;; c*) echo ')';;esac"),
+            vec![
+                "case", "$x", "in", "(", "a", "|", "b", ")", "ls", ";;", "c*", ")", "echo", ")",
+                ";;", "esac"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_status_param() {
+        let param = WordPart::Param(ParamExpansion::plain("?"));
+        assert_eq!(
+            tokenize(r#"echo $? "($?)" '$?' $"#).unwrap(),
+            vec![
+                Token::Word(Word::new(vec![WordPart::Literal("echo".to_string())])),
+                Token::Word(Word::new(vec![param.clone()])),
+                Token::Word(Word::new(vec![WordPart::DoubleQuoted(vec![
+                    WordPart::Literal("(".to_string()),
+                    param,
+                    WordPart::Literal(")".to_string()),
+                ])])),
+                Token::Word(Word::new(vec![WordPart::Quoted("$?".to_string())])),
+                Token::Word(Word::new(vec![WordPart::Literal("$".to_string())])),
+            ]
+        );
+    }
+
+    fn params(input: &str) -> Vec<ParamExpansion> {
+        match tokenize(input).unwrap().remove(0) {
+            Token::Word(word) => word
+                .parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    WordPart::Param(param) => Some(param),
+                    _ => None,
+                })
+                .collect(),
+            token => panic!("unexpected token {}", token),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_params() {
+        assert_eq!(
+            params("$HOME/${USER}_$1$$"),
+            vec![
+                ParamExpansion::plain("HOME"),
+                ParamExpansion::plain("USER"),
+                ParamExpansion::plain("1"),
+                ParamExpansion::plain("$"),
+            ]
+        );
+        assert_eq!(
+            params("${#PATH}${#}"),
+            vec![
+                ParamExpansion {
+                    name: "PATH".to_string(),
+                    op: ParamOp::Length,
+                },
+                ParamExpansion::plain("#"),
+            ]
+        );
+        assert_eq!(
+            params("${EDITOR:-vi}${x=$y}"),
+            vec![
+                ParamExpansion {
+                    name: "EDITOR".to_string(),
+                    op: ParamOp::Default(
+                        true,
+                        Word::new(vec![WordPart::Literal("vi".to_string())])
+                    ),
+                },
+                ParamExpansion {
+                    name: "x".to_string(),
+                    op: ParamOp::Assign(
+                        false,
+                        Word::new(vec![WordPart::Param(ParamExpansion::plain("y"))])
+                    ),
+                },
+            ]
+        );
+        // ${}の中の単語には空白やクォートを含められる
+        assert_eq!(
+            words("echo ${x:+a b} \"${y:?'not set'}\""),
+            vec!["echo", "${x:+a b}", "${y:?'not set'}"]
+        );
+        assert_eq!(words("echo $ a$/ cost$"), vec!["echo", "$", "a$/", "cost$"]);
+        assert_eq!(tokenize("echo ${x"), Err(LexError::UnterminatedBrace));
+        assert_eq!(tokenize("echo ${x y}"), Err(LexError::BadSubstitution));
+        assert_eq!(tokenize("echo ${%}"), Err(LexError::BadSubstitution));
+    }
+
+    #[test]
+    fn test_tokenize_redirect() {
+        assert_eq!(
+            tokenize("ls>out 2>>err 2>&1 &>all <in").unwrap()[1..],
+            [
+                Token::Redirect(None, RedirectOp::Great),
+                Token::Word(Word::new(vec![WordPart::Literal("out".to_string())])),
+                Token::Redirect(Some(2), RedirectOp::DGreat),
+                Token::Word(Word::new(vec![WordPart::Literal("err".to_string())])),
+                Token::Redirect(Some(2), RedirectOp::GreatAnd),
+                Token::Word(Word::new(vec![WordPart::Literal("1".to_string())])),
+                Token::Redirect(None, RedirectOp::AndGreat),
+                Token::Word(Word::new(vec![WordPart::Literal("all".to_string())])),
+                Token::Redirect(None, RedirectOp::Less),
+                Token::Word(Word::new(vec![WordPart::Literal("in".to_string())])),
+            ]
+        );
+        // fd番号として扱うのはリダイレクト演算子の直前に空白なしで書かれた数字だけ
+        assert_eq!(
+            words("echo 2 >out a2>b"),
+            vec!["echo", "2", ">", "out", "a2", ">", "b"]
+        );
+        assert_eq!(words("echo '2'>out"), vec!["echo", "2", ">", "out"]);
+    }
+
+    #[test]
+    fn test_tokenize_incomplete() {
+        assert_eq!(
+            tokenize("echo 'foo"),
+            Err(LexError::UnterminatedQuote('\''))
+        );
+        assert_eq!(
+            tokenize("echo \"foo\n"),
+            Err(LexError::UnterminatedQuote('"'))
+        );
+        assert_eq!(tokenize("echo foo \\\n"), Err(LexError::TrailingBackslash));
+        assert!(tokenize("echo \\").unwrap_err().is_incomplete());
+        assert_eq!(tokenize("echo $(ls 'a)'"), Err(LexError::UnterminatedParen));
+        assert_eq!(tokenize("echo `ls"), Err(LexError::UnterminatedQuote('`')));
+    }
+
+    #[test]
+    fn test_tokenize_command_subst() {
+        let subst = |s: &str| WordPart::CommandSubst(s.to_string());
+        let word = |parts: Vec<WordPart>| Token::Word(Word::new(parts));
+        assert_eq!(
+            tokenize("echo $(ls -l | wc) x$(echo ')' \")\")y").unwrap(),
+            vec![
+                word(vec![WordPart::Literal("echo".to_string())]),
+                word(vec![subst("ls -l | wc")]),
+                word(vec![
+                    WordPart::Literal("x".to_string()),
+                    subst("echo ')' \")\""),
+                    WordPart::Literal("y".to_string()),
+                ]),
+            ]
+        );
+        assert_eq!(
+            tokenize("\"$(case $x in a) echo;; (b) ls;; esac)\" `echo \\`date\\` \\a`").unwrap(),
+            vec![
+                word(vec![WordPart::DoubleQuoted(vec![subst(
+                    "case $x in a) echo;; (b) ls;; esac"
+                )])]),
+                word(vec![subst("echo `date` \\a")]),
+            ]
+        );
+        assert_eq!(
+            tokenize("echo $(\necho a\n)").unwrap()[1],
+            word(vec![subst("\necho a\n")])
+        );
+    }
+
+    #[test]
+    fn test_tokenize_arith() {
+        let literal = |s: &str| WordPart::Literal(s.to_string());
+        assert_eq!(
+            tokenize("echo $(( (x + $y) * 2 ))z").unwrap()[1],
+            Token::Word(Word::new(vec![
+                WordPart::Arith(vec![
+                    literal(" (x + "),
+                    WordPart::Param(ParamExpansion::plain("y")),
+                    literal(") * 2 "),
+                ]),
+                literal("z"),
+            ]))
+        );
+        assert_eq!(
+            tokenize("\"$((1 + $(echo 2)))\"").unwrap()[0],
+            Token::Word(Word::new(vec![WordPart::DoubleQuoted(vec![
+                WordPart::Arith(vec![
+                    literal("1 + "),
+                    WordPart::CommandSubst("echo 2".to_string()),
+                ])
+            ])]))
+        );
+        assert_eq!(tokenize("echo $((1 + 2)"), Err(LexError::BadSubstitution));
+        assert_eq!(tokenize("echo $((1 + (2"), Err(LexError::UnterminatedParen));
+    }
+
+    #[test]
+    fn test_tokenize_here_doc() {
+        let tokens =
+            tokenize("cat <<-'E F' <<X; ls\n\tq $x\n\tE F\n$x \\$y \"z\"\nX\necho").unwrap();
+        let here_doc = |i: usize| match &tokens[i] {
+            Token::HereDoc { delimiter, body } => (delimiter.unquote(), body.clone()),
+            token => panic!("unexpected token: {:?}", token),
+        };
+        assert_eq!(tokens[1], Token::Redirect(None, RedirectOp::DLessDash));
+        assert_eq!(
+            here_doc(2),
+            (
+                "E F".to_string(),
+                Word::new(vec![WordPart::Quoted("q $x\n".to_string())])
+            )
+        );
+        assert_eq!(
+            here_doc(4),
+            (
+                "X".to_string(),
+                Word::new(vec![WordPart::DoubleQuoted(vec![
+                    WordPart::Param(ParamExpansion::plain("x")),
+                    WordPart::Literal(" $y \"z\"\n".to_string()),
+                ])])
+            )
+        );
+        // 本文の後ろは普段通りにトークンに区切る
+        assert_eq!(tokens[5..], tokenize("; ls\necho").unwrap());
+
+        assert_eq!(
+            tokenize("cat <<EOF\nabc\n"),
+            Err(LexError::UnterminatedHereDoc("EOF".to_string()))
+        );
+        assert_eq!(
+            tokenize("cat <<EOF\n${x\nEOF\n"),
+            Err(LexError::BadSubstitution)
+        );
+        assert_eq!(
+            tokenize("cat <<< x").unwrap()[1],
+            Token::Redirect(None, RedirectOp::TLess)
+        );
+    }
+}
@@ -0,0 +1,29 @@
+// シェルの字句解析, パース, 実行をライブラリとして使えるようにしたもの
+// バイナリ(main.rs)は引数から入力元を決めて, replを回すだけ
+pub mod ast;
+pub mod error;
+pub mod executor;
+pub mod input;
+pub mod job;
+pub mod lexer;
+pub mod parser;
+pub mod repl;
+pub mod state;
+pub mod token;
+
+mod alias;
+mod arith;
+mod builtins;
+mod completion;
+mod condition;
+mod expand;
+mod history;
+mod printf;
+mod prompt;
+mod redirect;
+mod signal;
+
+pub use error::ShellError;
+pub use executor::Executor;
+pub use parser::parse;
+pub use state::ShellState;
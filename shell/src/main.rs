@@ -1,93 +1,63 @@
-use colored::*;
-use dirs::home_dir;
 use std::{
     env,
-    io::{stdin, stdout, Write},
-    path::Path,
-    process::{Child, Command, Stdio},
+    fs::File,
+    io::{BufReader, Cursor},
+    process,
 };
 
-fn main() {
-    loop {
-        let home_dir = home_dir().unwrap().display().to_string();
-        // 例えばhome_dirが/home/userの時、/home/user/workspace/home/userのようなディレクトリにいた場合バグるのでしっかりやるならstarts_withとか使うべき
-        let current_dir = env::current_dir()
-            .unwrap()
-            .display()
-            .to_string()
-            .replace(home_dir.as_str(), "~");
-
-        print!("{} {}", current_dir.blue().bold(), "$ ".white());
-        stdout().flush().unwrap();
-
-        let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+use shell::{error::describe, input::Input, repl, Executor, ShellState};
 
-        // もうちょっとオシャレにやりたい
-        if input == "\n" {
-            continue;
+fn main() {
+    let mut state = ShellState::new();
+    let args: Vec<String> = env::args().collect();
+    let mut input = match open_input(&mut state, &args) {
+        Ok(input) => input,
+        Err((message, status)) => {
+            eprintln!("shell: {}", message);
+            process::exit(status);
         }
+    };
+    let mut executor = Executor::new(state);
+    process::exit(repl::run(&mut executor, &mut input));
+}
 
-        // peekableは"consume"しないで次の値を覗き見することができるiterator. 名前のまんま
-        let mut commands = input.trim().split("|").peekable();
-        let mut previous_command = None;
-
-        // "|"で区切られたコマンドをiterateして処理する
-        while let Some(command) = commands.next() {
-            let mut parts = command.trim().split_whitespace();
-            let command = parts.next().unwrap();
-            let args = parts;
-
-            match command {
-                // cdは子プロセスに実行させたところで親プロセスの状態は何も変わらないため, 親プロセス自体が見ているディレクトリを変更する
-                "cd" => {
-                    let new_dir = args.peekable().peek().map_or("/", |x| *x);
-                    let root = Path::new(new_dir);
-                    if let Err(e) = env::set_current_dir(&root) {
-                        println!("{}", e);
-                    }
-                    previous_command = None;
-                }
-                "exit" => return,
-                // 例えばコマンドが`cat file.txt | grep something`の時を例にして考えてみる
-                _ => {
-                    // 1. previous_commandがNone, つまりcommandがcatの場合
-                    //     Stdio::inherit()で親プロセスの標準入力を受け取る -> terminalに表示されている画面のカーソルから打ち込まれた文字列が標準入力になる.
-                    //     ただしCommand::new()でcommandおよびargsがそれぞれcat, file.txtと指定されているため, これに関してはstdinを指定しなくても動く(確認済み)
-                    // 2. previout_commandがSome, つまりcommandがgrepの場合
-                    //     前回のコマンド`cat file.txt`が子プロセスで実行されており, その標準出力を標準入力にする. その結果grep somethingに対してfile.txtの内容が入力される.
-                    let stdin = previous_command.map_or(Stdio::inherit(), |output: Child| {
-                        Stdio::from(output.stdout.unwrap())
-                    });
-
-                    let stdout = if commands.peek().is_some() {
-                        // まだ後続にパイプ処理が残っている場合, 標準出力をpipeとして繋げる
-                        Stdio::piped()
-                    } else {
-                        // 最後のコマンドの場合, 標準出力を親プロセス(terminalの出力)の方に切り替える. でないと結果が画面に出力されない
-                        Stdio::inherit()
-                    };
-
-                    let output = Command::new(command)
-                        .args(args)
-                        .stdin(stdin)
-                        .stdout(stdout)
-                        .spawn();
-
-                    match output {
-                        Ok(output) => previous_command = Some(output),
-                        Err(e) => {
-                            previous_command = None;
-                            eprintln!("{}", e);
-                        }
-                    }
-                }
-            }
+// 引数に応じてコマンドの読み込み元を決める
+//   shell                       標準入力(端末なら対話モード)
+//   shell script.sh [args...]   スクリプトファイル
+//   shell -c 'cmd' [name [args...]]   -cの文字列. nameは$0になる
+// 開けなかった場合はエラーメッセージと終了ステータスを返す
+fn open_input(state: &mut ShellState, args: &[String]) -> Result<Input, (String, i32)> {
+    let program = args.first().cloned().unwrap_or_else(|| "shell".to_string());
+    let usage = || {
+        (
+            format!(
+                "usage: {} [-c command [name [args...]] | script [args...]]",
+                program
+            ),
+            2,
+        )
+    };
+
+    match args.get(1).map(|arg| arg.as_str()) {
+        None => {
+            state.script_name = program;
+            Ok(Input::new(state))
         }
-
-        if let Some(mut last_command) = previous_command {
-            // pipe最後のコマンドの処理実行が完了するまで待つ
-            last_command.wait().unwrap();
+        Some("-c") => {
+            let command = args.get(2).ok_or_else(usage)?;
+            state.script_name = args.get(3).cloned().unwrap_or(program);
+            state.positional = args.iter().skip(4).cloned().collect();
+            Ok(Input::from_reader(Box::new(Cursor::new(
+                command.clone().into_bytes(),
+            ))))
+        }
+        Some(arg) if arg.starts_with('-') => Err(usage()),
+        Some(script) => {
+            let file =
+                File::open(script).map_err(|e| (format!("{}: {}", script, describe(&e)), 127))?;
+            state.script_name = script.to_string();
+            state.positional = args.iter().skip(2).cloned().collect();
+            Ok(Input::from_reader(Box::new(BufReader::new(file))))
         }
     }
 }
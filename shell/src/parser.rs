@@ -0,0 +1,845 @@
+use std::{collections::HashMap, fmt::Display, iter::Peekable, rc::Rc, vec::IntoIter};
+
+use crate::{
+    alias::expand_aliases,
+    ast::{
+        AndOr, Assignment, CaseItem, Command, CompoundCommand, Connector, FunctionDef, List,
+        Pipeline, Redirect, RedirectKind, SimpleCommand,
+    },
+    lexer::{tokenize, LexError},
+    state::is_valid_name,
+    token::{RedirectOp, Token, Word, WordPart},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseError {
+    Lex(LexError),
+    UnexpectedToken(Token),
+    UnexpectedEof,
+    // `ls >`のように入力の末尾でリダイレクト先が無い場合. 続きの行は待たずにエラーにする
+    UnexpectedNewline,
+}
+
+impl ParseError {
+    // 次の行を読み足せばパースできる可能性があるかどうか. `ls |`のような入力は続きを待つ
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParseError::Lex(e) => e.is_incomplete(),
+            ParseError::UnexpectedEof => true,
+            ParseError::UnexpectedToken(_) | ParseError::UnexpectedNewline => false,
+        }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        ParseError::Lex(e)
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Lex(e) => write!(f, "syntax error: {}", e),
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "syntax error near unexpected token `{}'", token)
+            }
+            ParseError::UnexpectedEof => write!(f, "syntax error: unexpected end of file"),
+            ParseError::UnexpectedNewline => {
+                write!(f, "syntax error near unexpected token `newline'")
+            }
+        }
+    }
+}
+
+// 空の入力の場合はNoneを返す
+pub fn parse(input: &str) -> Result<Option<List>, ParseError> {
+    parse_tokens(tokenize(input)?)
+}
+
+// コマンド名の位置にあるエイリアスを展開してからパースする. 空の入力の場合はNoneを返す
+pub fn parse_with_aliases(
+    input: &str,
+    aliases: &HashMap<String, String>,
+) -> Result<Option<List>, ParseError> {
+    parse_tokens(expand_aliases(tokenize(input)?, aliases)?)
+}
+
+fn parse_tokens(tokens: Vec<Token>) -> Result<Option<List>, ParseError> {
+    let mut token_iter = tokens.into_iter().peekable();
+    let list = parse_list(&mut token_iter)?;
+    // `ls )`や`fi`のように, どの複合コマンドにも対応しない区切りが残っている
+    if let Some(token) = token_iter.next() {
+        return Err(ParseError::UnexpectedToken(token));
+    }
+    if list.and_ors.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(list))
+}
+
+// thenやdoneなど, 複合コマンドの中のリストの終わりを表す予約語
+const TERMINATORS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+// 複合コマンドの始まりを表す予約語
+const COMPOUND_STARTS: [&str; 6] = ["if", "while", "until", "for", "case", "{"];
+
+// 入力の終わりか, 複合コマンドの区切り(予約語, `;;`, `)`)の手前まで読む
+fn parse_list(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<List, ParseError> {
+    let mut and_ors = Vec::new();
+    loop {
+        skip_newlines(token_iter);
+        if at_list_end(token_iter) {
+            break;
+        }
+        let mut and_or = parse_and_or(token_iter)?;
+        match token_iter.peek() {
+            Some(Token::Semi | Token::Newline) => {
+                token_iter.next();
+            }
+            Some(Token::Amp) => {
+                token_iter.next();
+                and_or.background = true;
+            }
+            // 区切りが無い場合はここでリストが終わる. 続くトークンが正しいかは呼び出し元で判断する
+            _ => {
+                and_ors.push(and_or);
+                break;
+            }
+        }
+        and_ors.push(and_or);
+    }
+    Ok(List { and_ors })
+}
+
+fn at_list_end(token_iter: &mut Peekable<IntoIter<Token>>) -> bool {
+    match token_iter.peek() {
+        None | Some(Token::DSemi | Token::RParen) => true,
+        Some(Token::Word(word)) => word
+            .as_literal()
+            .is_some_and(|word| TERMINATORS.contains(&word)),
+        Some(_) => false,
+    }
+}
+
+// ifの条件やループの本体のように, 空であってはいけないリスト
+fn parse_compound_list(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<List, ParseError> {
+    let list = parse_list(token_iter)?;
+    if list.and_ors.is_empty() {
+        return match token_iter.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token)),
+            None => Err(ParseError::UnexpectedEof),
+        };
+    }
+    Ok(list)
+}
+
+fn parse_and_or(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<AndOr, ParseError> {
+    let first = parse_pipeline(token_iter)?;
+    let mut rest = Vec::new();
+    loop {
+        let connector = match token_iter.peek() {
+            Some(Token::AndIf) => Connector::And,
+            Some(Token::OrIf) => Connector::Or,
+            _ => break,
+        };
+        token_iter.next();
+        // `make &&`のように行末が&&や||で終わっている場合は次の行に続きが書ける
+        skip_newlines(token_iter);
+        rest.push((connector, parse_pipeline(token_iter)?));
+    }
+    Ok(AndOr {
+        first,
+        rest,
+        background: false,
+    })
+}
+
+fn parse_pipeline(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<Pipeline, ParseError> {
+    let negated = peek_keyword(token_iter, "!");
+    if negated {
+        token_iter.next();
+    }
+    let mut commands = vec![parse_command(token_iter)?];
+    while let Some(Token::Pipe) = token_iter.peek() {
+        token_iter.next();
+        skip_newlines(token_iter);
+        commands.push(parse_command(token_iter)?);
+    }
+    Ok(Pipeline { commands, negated })
+}
+
+fn skip_newlines(token_iter: &mut Peekable<IntoIter<Token>>) {
+    while let Some(Token::Newline) = token_iter.peek() {
+        token_iter.next();
+    }
+}
+
+// 次のトークンがクォートされていない予約語keywordかどうか. 予約語はコマンド名の位置でだけ意味を持つ
+fn peek_keyword(token_iter: &mut Peekable<IntoIter<Token>>, keyword: &str) -> bool {
+    match token_iter.peek() {
+        Some(Token::Word(word)) => word.as_literal() == Some(keyword),
+        _ => false,
+    }
+}
+
+fn expect_keyword(
+    token_iter: &mut Peekable<IntoIter<Token>>,
+    keyword: &str,
+) -> Result<(), ParseError> {
+    match token_iter.next() {
+        Some(Token::Word(word)) if word.as_literal() == Some(keyword) => Ok(()),
+        Some(token) => Err(ParseError::UnexpectedToken(token)),
+        None => Err(ParseError::UnexpectedEof),
+    }
+}
+
+fn expect_word(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<Word, ParseError> {
+    match token_iter.next() {
+        Some(Token::Word(word)) => Ok(word),
+        Some(token) => Err(ParseError::UnexpectedToken(token)),
+        None => Err(ParseError::UnexpectedEof),
+    }
+}
+
+fn parse_command(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<Command, ParseError> {
+    let keyword = match token_iter.peek() {
+        Some(Token::Word(word)) => word.as_literal().map(str::to_string),
+        _ => None,
+    };
+    let compound = match keyword.as_deref() {
+        Some("if") => parse_if(token_iter)?,
+        Some("while") => parse_while(token_iter, false)?,
+        Some("until") => parse_while(token_iter, true)?,
+        Some("for") => parse_for(token_iter)?,
+        Some("case") => parse_case(token_iter)?,
+        Some("{") => parse_group(token_iter)?,
+        _ if matches!(token_iter.peek(), Some(Token::LParen)) => parse_subshell(token_iter)?,
+        // `ls | done`のように区切りの予約語がコマンド名の位置に来るのは文法エラー
+        Some(word) if TERMINATORS.contains(&word) => {
+            let word = expect_word(token_iter)?;
+            return Err(ParseError::UnexpectedToken(Token::Word(word)));
+        }
+        _ => {
+            let command = parse_simple_command(token_iter)?;
+            if let Some(Token::LParen) = token_iter.peek() {
+                return parse_function(command, token_iter);
+            }
+            return Ok(Command::Simple(command));
+        }
+    };
+
+    let mut redirects = Vec::new();
+    while let Some(Token::Redirect(fd, op)) = token_iter.peek().cloned() {
+        token_iter.next();
+        redirects.push(parse_redirect(fd, op, token_iter)?);
+    }
+    Ok(Command::Compound(compound, redirects))
+}
+
+// name() { ... }. 本体には複合コマンドを1つ書ける
+fn parse_function(
+    command: SimpleCommand,
+    token_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<Command, ParseError> {
+    let name = match command.words.as_slice() {
+        [word] if command.assignments.is_empty() && command.redirects.is_empty() => {
+            word.as_literal().map(str::to_string)
+        }
+        _ => None,
+    };
+    let (Some(name), Some(Token::LParen)) = (name, token_iter.next()) else {
+        return Err(ParseError::UnexpectedToken(Token::LParen));
+    };
+    match token_iter.next() {
+        Some(Token::RParen) => {}
+        Some(token) => return Err(ParseError::UnexpectedToken(token)),
+        None => return Err(ParseError::UnexpectedEof),
+    }
+
+    skip_newlines(token_iter);
+    let is_compound = match token_iter.peek() {
+        Some(Token::Word(word)) => word
+            .as_literal()
+            .is_some_and(|word| COMPOUND_STARTS.contains(&word)),
+        Some(Token::LParen) => true,
+        _ => false,
+    };
+    if !is_compound {
+        return match token_iter.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token)),
+            None => Err(ParseError::UnexpectedEof),
+        };
+    }
+    match parse_command(token_iter)? {
+        Command::Compound(body, redirects) => Ok(Command::Function(FunctionDef {
+            name,
+            body: Rc::new(body),
+            redirects,
+        })),
+        _ => unreachable!(),
+    }
+}
+
+// { list; }. 閉じ括弧の}は`;`や改行の後ろに書く必要がある
+fn parse_group(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<CompoundCommand, ParseError> {
+    expect_keyword(token_iter, "{")?;
+    let list = parse_compound_list(token_iter)?;
+    expect_keyword(token_iter, "}")?;
+    Ok(CompoundCommand::Group(list))
+}
+
+// ( list ). }と違って)は演算子なので, `(ls)`のように区切り無しで書ける
+fn parse_subshell(
+    token_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<CompoundCommand, ParseError> {
+    token_iter.next();
+    let list = parse_compound_list(token_iter)?;
+    match token_iter.next() {
+        Some(Token::RParen) => Ok(CompoundCommand::Subshell(list)),
+        Some(token) => Err(ParseError::UnexpectedToken(token)),
+        None => Err(ParseError::UnexpectedEof),
+    }
+}
+
+// if c1; then b1; elif c2; then b2; else b3; fi
+fn parse_if(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<CompoundCommand, ParseError> {
+    expect_keyword(token_iter, "if")?;
+    let mut conditions = Vec::new();
+    let mut else_body = None;
+    loop {
+        let condition = parse_compound_list(token_iter)?;
+        expect_keyword(token_iter, "then")?;
+        let body = parse_compound_list(token_iter)?;
+        conditions.push((condition, body));
+
+        match token_iter.next() {
+            Some(Token::Word(word)) if word.as_literal() == Some("elif") => continue,
+            Some(Token::Word(word)) if word.as_literal() == Some("else") => {
+                else_body = Some(parse_compound_list(token_iter)?);
+                expect_keyword(token_iter, "fi")?;
+                break;
+            }
+            Some(Token::Word(word)) if word.as_literal() == Some("fi") => break,
+            Some(token) => return Err(ParseError::UnexpectedToken(token)),
+            None => return Err(ParseError::UnexpectedEof),
+        }
+    }
+    Ok(CompoundCommand::If {
+        conditions,
+        else_body,
+    })
+}
+
+// while c; do b; done, until c; do b; done
+fn parse_while(
+    token_iter: &mut Peekable<IntoIter<Token>>,
+    until: bool,
+) -> Result<CompoundCommand, ParseError> {
+    token_iter.next();
+    let condition = parse_compound_list(token_iter)?;
+    expect_keyword(token_iter, "do")?;
+    let body = parse_compound_list(token_iter)?;
+    expect_keyword(token_iter, "done")?;
+    Ok(CompoundCommand::While {
+        condition,
+        body,
+        until,
+    })
+}
+
+// for name in words; do b; done. `for name; do`や`for name do`のようにinを省略してもよい
+fn parse_for(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<CompoundCommand, ParseError> {
+    expect_keyword(token_iter, "for")?;
+    let word = expect_word(token_iter)?;
+    let name = match word.as_literal() {
+        Some(name) if is_valid_name(name) => name.to_string(),
+        _ => return Err(ParseError::UnexpectedToken(Token::Word(word))),
+    };
+
+    skip_newlines(token_iter);
+    let words = if peek_keyword(token_iter, "in") {
+        token_iter.next();
+        let mut words = Vec::new();
+        while let Some(Token::Word(_)) = token_iter.peek() {
+            words.push(expect_word(token_iter)?);
+        }
+        match token_iter.next() {
+            Some(Token::Semi | Token::Newline) => {}
+            Some(token) => return Err(ParseError::UnexpectedToken(token)),
+            None => return Err(ParseError::UnexpectedEof),
+        }
+        Some(words)
+    } else {
+        if let Some(Token::Semi) = token_iter.peek() {
+            token_iter.next();
+        }
+        None
+    };
+
+    skip_newlines(token_iter);
+    expect_keyword(token_iter, "do")?;
+    let body = parse_compound_list(token_iter)?;
+    expect_keyword(token_iter, "done")?;
+    Ok(CompoundCommand::For { name, words, body })
+}
+
+// case word in (p1 | p2) b1;; p3) b2;; esac. 最後の;;とパターンの前の(は省略できる
+fn parse_case(token_iter: &mut Peekable<IntoIter<Token>>) -> Result<CompoundCommand, ParseError> {
+    expect_keyword(token_iter, "case")?;
+    let word = expect_word(token_iter)?;
+    skip_newlines(token_iter);
+    expect_keyword(token_iter, "in")?;
+
+    let mut items = Vec::new();
+    loop {
+        skip_newlines(token_iter);
+        if peek_keyword(token_iter, "esac") {
+            token_iter.next();
+            break;
+        }
+        if let Some(Token::LParen) = token_iter.peek() {
+            token_iter.next();
+        }
+        let mut patterns = vec![expect_word(token_iter)?];
+        while let Some(Token::Pipe) = token_iter.peek() {
+            token_iter.next();
+            patterns.push(expect_word(token_iter)?);
+        }
+        match token_iter.next() {
+            Some(Token::RParen) => {}
+            Some(token) => return Err(ParseError::UnexpectedToken(token)),
+            None => return Err(ParseError::UnexpectedEof),
+        }
+
+        let body = parse_list(token_iter)?;
+        items.push(CaseItem { patterns, body });
+        if peek_keyword(token_iter, "esac") {
+            continue;
+        }
+        match token_iter.next() {
+            Some(Token::DSemi) => {}
+            Some(token) => return Err(ParseError::UnexpectedToken(token)),
+            None => return Err(ParseError::UnexpectedEof),
+        }
+    }
+    Ok(CompoundCommand::Case { word, items })
+}
+
+fn parse_simple_command(
+    token_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<SimpleCommand, ParseError> {
+    let mut assignments = Vec::new();
+    let mut words = Vec::new();
+    let mut redirects = Vec::new();
+    while let Some(Token::Word(_) | Token::Redirect(..)) = token_iter.peek() {
+        match token_iter.next() {
+            // コマンド名より前にある`NAME=value`の形の単語だけを代入として扱う. `echo a=b`のa=bはただの引数
+            Some(Token::Word(word)) if words.is_empty() => match parse_assignment(word) {
+                Ok(assignment) => assignments.push(assignment),
+                Err(word) => words.push(word),
+            },
+            Some(Token::Word(word)) => words.push(word),
+            Some(Token::Redirect(fd, op)) => redirects.push(parse_redirect(fd, op, token_iter)?),
+            _ => unreachable!(),
+        }
+    }
+
+    if assignments.is_empty() && words.is_empty() && redirects.is_empty() {
+        return match token_iter.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token)),
+            None => Err(ParseError::UnexpectedEof),
+        };
+    }
+    Ok(SimpleCommand {
+        assignments,
+        words,
+        redirects,
+    })
+}
+
+// 単語がクォートされていない`NAME=`で始まっていれば代入として解釈する. そうでなければ単語をそのまま返す
+fn parse_assignment(word: Word) -> Result<Assignment, Word> {
+    let name = match word.parts.first() {
+        Some(WordPart::Literal(s)) => match s.split_once('=') {
+            Some((name, _)) if is_valid_name(name) => name.to_string(),
+            _ => return Err(word),
+        },
+        _ => return Err(word),
+    };
+
+    let mut parts = word.parts;
+    if let WordPart::Literal(s) = &mut parts[0] {
+        s.drain(..=name.len());
+        if s.is_empty() {
+            parts.remove(0);
+        }
+    }
+    Ok(Assignment {
+        name,
+        value: Word::new(parts),
+    })
+}
+
+fn parse_redirect(
+    fd: Option<u32>,
+    op: RedirectOp,
+    token_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<Redirect, ParseError> {
+    let (target, body) = match token_iter.next() {
+        Some(Token::HereDoc { delimiter, body })
+            if matches!(op, RedirectOp::DLess | RedirectOp::DLessDash) =>
+        {
+            (delimiter, Some(body))
+        }
+        Some(Token::Word(word)) if !matches!(op, RedirectOp::DLess | RedirectOp::DLessDash) => {
+            (word, None)
+        }
+        Some(token) => return Err(ParseError::UnexpectedToken(token)),
+        None => return Err(ParseError::UnexpectedNewline),
+    };
+
+    let (default_fd, kind) = match op {
+        RedirectOp::Less => (0, RedirectKind::Input),
+        RedirectOp::Great => (1, RedirectKind::Output),
+        RedirectOp::DGreat => (1, RedirectKind::Append),
+        RedirectOp::LessAnd => (0, RedirectKind::DuplicateInput),
+        RedirectOp::GreatAnd => (1, RedirectKind::Duplicate),
+        RedirectOp::AndGreat => (1, RedirectKind::OutputAll),
+        RedirectOp::AndDGreat => (1, RedirectKind::AppendAll),
+        RedirectOp::DLess => (0, RedirectKind::HereDoc),
+        RedirectOp::DLessDash => (0, RedirectKind::HereDocStripTabs),
+        RedirectOp::TLess => (0, RedirectKind::HereString),
+    };
+
+    Ok(Redirect {
+        fd: fd.unwrap_or(default_fd),
+        kind,
+        target,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_pipeline(input: &str) -> Pipeline {
+        let mut list = parse(input).unwrap().unwrap();
+        assert_eq!(list.and_ors.len(), 1);
+        let and_or = list.and_ors.remove(0);
+        assert!(and_or.rest.is_empty());
+        and_or.first
+    }
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(command) => command,
+            command => panic!("not a simple command: {}", command),
+        }
+    }
+
+    fn command_words(pipeline: &Pipeline) -> Vec<Vec<String>> {
+        pipeline
+            .commands
+            .iter()
+            .map(|command| {
+                let words = &simple(command).words;
+                words.iter().map(|word| word.unquote()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse("   \n"), Ok(None));
+        assert_eq!(parse("# comment only\n"), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        let pipeline = parse_pipeline("cat file.txt | grep 'foo | bar' |\n wc -l\n");
+        assert_eq!(
+            command_words(&pipeline),
+            vec![
+                vec!["cat", "file.txt"],
+                vec!["grep", "foo | bar"],
+                vec!["wc", "-l"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        let pipeline = parse_pipeline("FOO=1 BAR= BAZ=\"a b\"$x >out cmd X=2");
+        let command = simple(&pipeline.commands[0]);
+        let assignments: Vec<(String, String)> = command
+            .assignments
+            .iter()
+            .map(|a| (a.name.clone(), a.value.unquote()))
+            .collect();
+        assert_eq!(
+            assignments,
+            vec![
+                ("FOO".to_string(), "1".to_string()),
+                ("BAR".to_string(), "".to_string()),
+                ("BAZ".to_string(), "a b$x".to_string()),
+            ]
+        );
+        assert_eq!(command_words(&pipeline), vec![vec!["cmd", "X=2"]]);
+        assert_eq!(command.redirects.len(), 1);
+
+        // クォートされていたり名前として不正だったりするものは代入ではない
+        let pipeline = parse_pipeline("'FOO=1' 1X=2 =3");
+        assert!(simple(&pipeline.commands[0]).assignments.is_empty());
+
+        let pipeline = parse_pipeline("FOO=1");
+        assert_eq!(simple(&pipeline.commands[0]).assignments.len(), 1);
+        assert!(simple(&pipeline.commands[0]).words.is_empty());
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let pipeline = parse_pipeline("sort < in.txt 2>&1 | uniq >> out.txt");
+        assert_eq!(command_words(&pipeline), vec![vec!["sort"], vec!["uniq"]]);
+        let redirects = |i: usize| -> Vec<(u32, RedirectKind, String)> {
+            simple(&pipeline.commands[i])
+                .redirects
+                .iter()
+                .map(|r| (r.fd, r.kind, r.target.unquote()))
+                .collect()
+        };
+        assert_eq!(
+            redirects(0),
+            vec![
+                (0, RedirectKind::Input, "in.txt".to_string()),
+                (2, RedirectKind::Duplicate, "1".to_string()),
+            ]
+        );
+        assert_eq!(
+            redirects(1),
+            vec![(1, RedirectKind::Append, "out.txt".to_string())]
+        );
+
+        // <&は>&と同じくfdを複製するが, 表示する時は入力された向きに戻す
+        let pipeline = parse_pipeline("cat <&3 4<&0 2>&1");
+        let kinds: Vec<(u32, RedirectKind, String)> = simple(&pipeline.commands[0])
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.kind, r.target.unquote()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, RedirectKind::DuplicateInput, "3".to_string()),
+                (4, RedirectKind::DuplicateInput, "0".to_string()),
+                (2, RedirectKind::Duplicate, "1".to_string()),
+            ]
+        );
+        assert_eq!(pipeline.to_string(), "cat <&3 4<&0 2>&1");
+
+        // コマンド名が無くリダイレクトだけでもよい
+        let pipeline = parse_pipeline("> empty.txt");
+        assert!(simple(&pipeline.commands[0]).words.is_empty());
+        assert_eq!(simple(&pipeline.commands[0]).redirects.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_here_doc() {
+        let list = parse("cat <<EOF 3<<<'a b' | wc\nline $x\nEOF\necho done")
+            .unwrap()
+            .unwrap();
+        assert_eq!(list.and_ors.len(), 2);
+        let pipeline = &list.and_ors[0].first;
+        let redirects: Vec<(u32, RedirectKind, String, Option<String>)> =
+            simple(&pipeline.commands[0])
+                .redirects
+                .iter()
+                .map(|r| {
+                    let body = r.body.as_ref().map(|body| body.unquote());
+                    (r.fd, r.kind, r.target.unquote(), body)
+                })
+                .collect();
+        assert_eq!(
+            redirects,
+            vec![
+                (
+                    0,
+                    RedirectKind::HereDoc,
+                    "EOF".to_string(),
+                    Some("line $x\n".to_string())
+                ),
+                (3, RedirectKind::HereString, "a b".to_string(), None),
+            ]
+        );
+        assert_eq!(pipeline.to_string(), "cat <<EOF 3<<<'a b' | wc");
+
+        // <<-は先頭のタブを取り除いた本文を持ち, 表示する時も<<-に戻す
+        let pipeline = parse_pipeline("cat <<-EOF\n\tline\n\tEOF\n");
+        let redirect = &simple(&pipeline.commands[0]).redirects[0];
+        assert_eq!(redirect.kind, RedirectKind::HereDocStripTabs);
+        assert_eq!(
+            redirect.body.as_ref().map(|body| body.unquote()),
+            Some("line\n".to_string())
+        );
+        assert_eq!(pipeline.to_string(), "cat <<-EOF");
+
+        assert_eq!(
+            parse("cat <<EOF\nline"),
+            Err(ParseError::Lex(LexError::UnterminatedHereDoc(
+                "EOF".to_string()
+            )))
+        );
+        assert_eq!(
+            parse("cat <<\n"),
+            Err(ParseError::UnexpectedToken(Token::Newline))
+        );
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = parse("make && ./a.out ||\n echo failed; echo $?\n\nls;")
+            .unwrap()
+            .unwrap();
+        assert_eq!(list.and_ors.len(), 3);
+
+        let connectors: Vec<Connector> = list.and_ors[0].rest.iter().map(|(c, _)| *c).collect();
+        assert_eq!(connectors, vec![Connector::And, Connector::Or]);
+        assert_eq!(
+            command_words(&list.and_ors[0].rest[1].1),
+            vec![vec!["echo", "failed"]]
+        );
+        assert_eq!(
+            command_words(&list.and_ors[1].first),
+            vec![vec!["echo", "$?"]]
+        );
+        assert_eq!(command_words(&list.and_ors[2].first), vec![vec!["ls"]]);
+    }
+
+    #[test]
+    fn test_parse_background() {
+        let list = parse("sleep 10 & make && ./a.out &\nls").unwrap().unwrap();
+        let background: Vec<bool> = list.and_ors.iter().map(|a| a.background).collect();
+        assert_eq!(background, vec![true, true, false]);
+        assert_eq!(list.and_ors[1].to_string(), "make && ./a.out");
+    }
+
+    #[test]
+    fn test_parse_compound() {
+        let display = |input: &str| parse(input).unwrap().unwrap().to_string();
+        assert_eq!(
+            display("if test -f a; then cat a\nelif false\nthen :; else echo none; fi"),
+            "if test -f a; then cat a; elif false; then :; else echo none; fi;"
+        );
+        assert_eq!(
+            display("while read line\ndo\n  echo $line\ndone < in.txt"),
+            "while read line; do echo $line; done <in.txt;"
+        );
+        assert_eq!(
+            display("until false; do break; done"),
+            "until false; do break; done;"
+        );
+        assert_eq!(
+            display("for f in *.rs 'a b'\ndo echo $f; done | sort"),
+            "for f in *.rs 'a b'; do echo $f; done | sort;"
+        );
+        assert_eq!(display("for arg do echo; done"), "for arg; do echo; done;");
+        assert_eq!(
+            display("case $1 in\n  (a|b) echo ab;;\n  *.txt) ;;\n  *) echo other\nesac"),
+            "case $1 in a | b) echo ab;; *.txt) ;; *) echo other;; esac;"
+        );
+        assert_eq!(
+            display("! if true; then false; fi && echo done"),
+            "! if true; then false; fi && echo done;"
+        );
+
+        assert_eq!(
+            display("greet() {\n  echo hi $1\n} > out\n{ ls; pwd; } | wc -l"),
+            "greet() { echo hi $1; } >out; { ls; pwd; } | wc -l;"
+        );
+        assert_eq!(
+            display("f()\nwhile true; do :; done"),
+            "f() while true; do :; done;"
+        );
+        assert_eq!(
+            display("(cd /tmp; ls) 2>/dev/null | wc -l\nf() (echo sub)"),
+            "( cd /tmp; ls; ) 2>/dev/null | wc -l; f() ( echo sub; );"
+        );
+
+        // 予約語はコマンド名の位置にある場合だけ特別な意味を持つ
+        assert_eq!(
+            command_words(&parse_pipeline("echo if then 'fi'")),
+            vec![vec!["echo", "if", "then", "fi"]]
+        );
+        let pipeline = parse_pipeline("'if' true");
+        assert_eq!(command_words(&pipeline), vec![vec!["if", "true"]]);
+    }
+
+    #[test]
+    fn test_parse_compound_error() {
+        assert!(parse("if true").unwrap_err().is_incomplete());
+        assert!(parse("if true; then\n").unwrap_err().is_incomplete());
+        assert!(parse("for i in a b\ndo echo $i")
+            .unwrap_err()
+            .is_incomplete());
+        assert!(parse("case x in\n a) ls;;").unwrap_err().is_incomplete());
+        assert!(parse("(echo a\necho b").unwrap_err().is_incomplete());
+
+        let keyword = |s: &str| Token::Word(Word::new(vec![WordPart::Literal(s.to_string())]));
+        assert_eq!(
+            parse("if then fi"),
+            Err(ParseError::UnexpectedToken(keyword("then")))
+        );
+        assert_eq!(
+            parse("while true; do done"),
+            Err(ParseError::UnexpectedToken(keyword("done")))
+        );
+        assert_eq!(
+            parse("echo a; fi"),
+            Err(ParseError::UnexpectedToken(keyword("fi")))
+        );
+        assert_eq!(
+            parse("for 1x in a; do :; done"),
+            Err(ParseError::UnexpectedToken(keyword("1x")))
+        );
+        assert_eq!(
+            parse("ls )"),
+            Err(ParseError::UnexpectedToken(Token::RParen))
+        );
+        assert_eq!(parse("()"), Err(ParseError::UnexpectedToken(Token::RParen)));
+        assert_eq!(
+            parse("(echo a; })"),
+            Err(ParseError::UnexpectedToken(keyword("}")))
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(
+            parse("ls | | wc"),
+            Err(ParseError::UnexpectedToken(Token::Pipe))
+        );
+        assert_eq!(parse("| wc"), Err(ParseError::UnexpectedToken(Token::Pipe)));
+        assert!(parse("ls |").unwrap_err().is_incomplete());
+        assert_eq!(parse("ls >"), Err(ParseError::UnexpectedNewline));
+        assert_eq!(
+            parse("ls >\n"),
+            Err(ParseError::UnexpectedToken(Token::Newline))
+        );
+        assert_eq!(
+            parse("ls ;; ls"),
+            Err(ParseError::UnexpectedToken(Token::DSemi))
+        );
+        assert_eq!(
+            parse("&& ls"),
+            Err(ParseError::UnexpectedToken(Token::AndIf))
+        );
+        assert!(parse("make &&").unwrap_err().is_incomplete());
+        assert!(parse("make ||\n").unwrap_err().is_incomplete());
+        assert_eq!(
+            parse("ls > | wc"),
+            Err(ParseError::UnexpectedToken(Token::Pipe))
+        );
+        assert!(parse("echo 'foo").unwrap_err().is_incomplete());
+    }
+}
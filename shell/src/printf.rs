@@ -0,0 +1,575 @@
+// printfの書式の展開と, echo -eやprintfで使うバックスラッシュのエスケープ
+use std::{iter::Peekable, str::CharIndices};
+
+// 幅と精度の上限. これより大きいと埋める文字列を確保できずにシェルごと落ちるので, エラーにして変換を飛ばす
+const MAX_FIELD: i64 = 1 << 20;
+
+// 書式の%の後ろに書かれた変換指定
+#[derive(Debug, Default)]
+struct Spec {
+    // -: 左詰め
+    left: bool,
+    // +: 正の数にも符号を付ける
+    plus: bool,
+    // 空白: 正の数の前に空白を付ける
+    space: bool,
+    // #: 8進数に0, 16進数に0xを付ける. %gでは末尾の0を残す
+    alt: bool,
+    // 0: 幅に足りない分を0で埋める
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// 書式formatに引数argsを当てはめた結果と, 数値として解釈できなかった引数などのエラーメッセージを返す
+// 書式が引数を使い切らない場合は, 引数が無くなるまで書式を繰り返す. 足りない引数は空文字列か0として扱う
+// エラーがあった場合もできるところまで出力する
+pub fn printf(format: &str, args: &[String]) -> (Vec<u8>, Vec<String>) {
+    let mut printer = Printer {
+        args,
+        next: 0,
+        output: Vec::new(),
+        errors: Vec::new(),
+    };
+    loop {
+        let start = printer.next;
+        if !printer.format(format) {
+            break;
+        }
+        if printer.next >= args.len() || printer.next == start {
+            break;
+        }
+    }
+    (printer.output, printer.errors)
+}
+
+struct Printer<'a> {
+    args: &'a [String],
+    // 次に使う引数の位置
+    next: usize,
+    output: Vec<u8>,
+    errors: Vec<String>,
+}
+
+impl Printer<'_> {
+    fn next_arg(&mut self) -> &str {
+        match self.args.get(self.next) {
+            Some(arg) => {
+                self.next += 1;
+                arg
+            }
+            None => "",
+        }
+    }
+
+    // 書式を1回分出力する. \cや不正な変換指定で出力を打ち切る場合はfalseを返す
+    fn format(&mut self, format: &str) -> bool {
+        let mut rest = format;
+        while !rest.is_empty() {
+            let literal_end = rest.find('%').unwrap_or(rest.len());
+            if !expand_escapes(&rest[..literal_end], false, &mut self.output) {
+                return false;
+            }
+            rest = &rest[literal_end..];
+            let Some(after_percent) = rest.strip_prefix('%') else {
+                break;
+            };
+            if let Some(after) = after_percent.strip_prefix('%') {
+                self.output.push(b'%');
+                rest = after;
+                continue;
+            }
+            match self.conversion(after_percent) {
+                Some(after) => rest = after,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // %の後ろの変換指定を1つ読んで出力し, 書式の残りを返す. 出力を打ち切る場合はNoneを返す
+    fn conversion<'f>(&mut self, format: &'f str) -> Option<&'f str> {
+        let mut spec = Spec::default();
+        let mut chars = format.char_indices().peekable();
+        while let Some(&(_, c)) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alt = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            chars.next();
+        }
+
+        // 負の幅は左詰めになる
+        let width = self.number(&mut chars);
+        spec.left |= width < 0;
+        spec.width = width.unsigned_abs() as usize;
+        let precision = chars
+            .next_if(|&(_, c)| c == '.')
+            .map(|_| self.number(&mut chars).max(0));
+        spec.precision = precision.map(|precision| precision as usize);
+
+        let Some((i, conversion)) = chars.next() else {
+            self.errors.push("missing format character".to_string());
+            return None;
+        };
+        let rest = &format[i + conversion.len_utf8()..];
+        let out_of_range = if width.unsigned_abs() > MAX_FIELD as u64 {
+            Some(format!("{}: invalid field width", width))
+        } else {
+            precision
+                .filter(|&precision| precision > MAX_FIELD)
+                .map(|precision| format!("{}: Numerical result out of range", precision))
+        };
+        if let Some(e) = out_of_range {
+            self.errors.push(e);
+            self.next_arg();
+            return Some(rest);
+        }
+        match conversion {
+            's' => {
+                let arg = self.next_arg();
+                let arg = match spec.precision {
+                    Some(precision) => arg.chars().take(precision).collect(),
+                    None => arg.to_string(),
+                };
+                let padded = pad(&spec, "", &arg, false);
+                self.output.extend(padded.as_bytes());
+            }
+            'c' => {
+                let arg: String = self.next_arg().chars().take(1).collect();
+                let padded = pad(&spec, "", &arg, false);
+                self.output.extend(padded.as_bytes());
+            }
+            // %bは引数の中のエスケープを展開する. 引数の中の\cでは出力全体を打ち切る
+            'b' => {
+                let mut expanded = Vec::new();
+                let arg = self.next_arg().to_string();
+                let finished = expand_escapes(&arg, true, &mut expanded);
+                let fill = spec.width.saturating_sub(expanded.len());
+                if !spec.left {
+                    self.output.resize(self.output.len() + fill, b' ');
+                }
+                self.output.extend(expanded);
+                if spec.left {
+                    self.output.resize(self.output.len() + fill, b' ');
+                }
+                if !finished {
+                    return None;
+                }
+            }
+            'd' | 'i' => {
+                let arg = self.next_arg().to_string();
+                let value = self.integer(&arg);
+                let digits = with_precision(value.unsigned_abs().to_string(), spec.precision);
+                let sign = sign(&spec, value < 0);
+                let padded = pad(&spec, sign, &digits, spec.precision.is_none());
+                self.output.extend(padded.as_bytes());
+            }
+            'u' | 'o' | 'x' | 'X' => {
+                let arg = self.next_arg().to_string();
+                // 負の数は2の補数の表現のまま符号無しとして扱う
+                let value = self.integer(&arg) as u64;
+                let digits = match conversion {
+                    'u' => value.to_string(),
+                    'o' => format!("{:o}", value),
+                    'x' => format!("{:x}", value),
+                    _ => format!("{:X}", value),
+                };
+                let digits = with_precision(digits, spec.precision);
+                let prefix = match conversion {
+                    'o' if spec.alt && !digits.starts_with('0') => "0",
+                    'x' if spec.alt && value != 0 => "0x",
+                    'X' if spec.alt && value != 0 => "0X",
+                    _ => "",
+                };
+                let padded = pad(&spec, prefix, &digits, spec.precision.is_none());
+                self.output.extend(padded.as_bytes());
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let arg = self.next_arg().to_string();
+                let value = self.float(&arg);
+                let body = format_float(value.abs(), conversion, spec.precision, spec.alt);
+                let sign = sign(&spec, value.is_sign_negative() && !value.is_nan());
+                let padded = pad(&spec, sign, &body, value.is_finite());
+                self.output.extend(padded.as_bytes());
+            }
+            c => {
+                self.errors
+                    .push(format!("%{}: invalid format character", c));
+                return None;
+            }
+        }
+        Some(rest)
+    }
+
+    // 変換指定の幅や精度. *の場合は引数から取る
+    fn number(&mut self, chars: &mut Peekable<CharIndices>) -> i64 {
+        if chars.next_if(|&(_, c)| c == '*').is_some() {
+            let arg = self.next_arg().to_string();
+            return self.integer(&arg);
+        }
+        let mut value = 0i64;
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            value = value
+                .saturating_mul(10)
+                .saturating_add(i64::from(c as u8 - b'0'));
+        }
+        value
+    }
+
+    // 整数の引数. 0xで始まれば16進数, 0で始まれば8進数, 'か"で始まればその次の文字のコードとして扱う
+    fn integer(&mut self, arg: &str) -> i64 {
+        match parse_integer(arg) {
+            Some(value) => value,
+            None => {
+                self.errors.push(format!("{}: invalid number", arg));
+                0
+            }
+        }
+    }
+
+    fn float(&mut self, arg: &str) -> f64 {
+        if let Some(code) = char_code(arg) {
+            return code as f64;
+        }
+        let trimmed = arg.trim();
+        if trimmed.is_empty() {
+            return 0.0;
+        }
+        match trimmed.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                self.errors.push(format!("{}: invalid number", arg));
+                0.0
+            }
+        }
+    }
+}
+
+fn char_code(arg: &str) -> Option<u32> {
+    let rest = arg.strip_prefix(['\'', '"'])?;
+    Some(rest.chars().next().map_or(0, u32::from))
+}
+
+fn parse_integer(arg: &str) -> Option<i64> {
+    if let Some(code) = char_code(arg) {
+        return Some(i64::from(code));
+    }
+    let trimmed = arg.trim();
+    if trimmed.is_empty() {
+        return Some(0);
+    }
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    let value = value as i64;
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+// 整数の精度は最低限の桁数. 足りなければ先頭を0で埋める. 精度0で値が0の場合は何も出力しない
+fn with_precision(digits: String, precision: Option<usize>) -> String {
+    match precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits,
+    }
+}
+
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+// 幅に足りない分を空白で埋める. 数値で0フラグがあれば符号や0xの後ろを0で埋める
+fn pad(spec: &Spec, prefix: &str, body: &str, zero_fill: bool) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if spec.zero && zero_fill {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
+
+// 符号を除いた浮動小数点数. 精度のデフォルトは6桁
+fn format_float(value: f64, conversion: char, precision: Option<usize>, alt: bool) -> String {
+    let upper = conversion.is_ascii_uppercase();
+    let body = if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        "inf".to_string()
+    } else {
+        let precision = precision.unwrap_or(6);
+        match conversion.to_ascii_lowercase() {
+            'f' => format!("{:.*}", precision, value),
+            'e' => format_exponent(value, precision),
+            // 指数が-4より小さいか精度以上なら%e, それ以外は%fの形式にする. #が無ければ末尾の0は取り除く
+            _ => {
+                let precision = precision.max(1);
+                let exponent = exponent(value, precision - 1);
+                let formatted = if exponent < -4 || exponent >= precision as i32 {
+                    format_exponent(value, precision - 1)
+                } else {
+                    format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+                };
+                if alt {
+                    formatted
+                } else {
+                    strip_trailing_zeros(&formatted)
+                }
+            }
+        }
+    };
+    if upper {
+        body.to_uppercase()
+    } else {
+        body
+    }
+}
+
+// Rustの{:e}は`1.5e2`の形式なので, Cと同じく指数に符号と最低2桁を付けた`1.5e+02`の形式にする
+fn format_exponent(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+// 有効数字precision + 1桁に丸めた時の10進数の指数
+fn exponent(value: f64, precision: usize) -> i32 {
+    let formatted = format!("{:.*e}", precision, value);
+    formatted
+        .split_once('e')
+        .and_then(|(_, exponent)| exponent.parse().ok())
+        .unwrap_or(0)
+}
+
+fn strip_trailing_zeros(formatted: &str) -> String {
+    let (mantissa, exponent) = match formatted.find('e') {
+        Some(i) => formatted.split_at(i),
+        None => (formatted, ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+// バックスラッシュのエスケープを展開してoutputに追加する. \cが出てきたらそこで打ち切ってfalseを返す
+// 8進数はecho -eとprintfの%bでは\0nnn, printfの書式では\nnnの形で書く(octal_zero)
+// 知らないエスケープはバックスラッシュごとそのまま残す
+pub fn expand_escapes(s: &str, octal_zero: bool, output: &mut Vec<u8>) -> bool {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 == bytes.len() {
+            output.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let escape = bytes[i + 1];
+        i += 2;
+        let byte = match escape {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'e' | b'E' => 0x1b,
+            b'f' => 0x0c,
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'v' => 0x0b,
+            b'\\' => b'\\',
+            b'"' if !octal_zero => b'"',
+            b'c' => return false,
+            b'x' => {
+                let (value, len) = parse_digits(&bytes[i..], 16, 2);
+                if len == 0 {
+                    output.extend(b"\\x");
+                    continue;
+                }
+                i += len;
+                value as u8
+            }
+            b'0' if octal_zero => {
+                let (value, len) = parse_digits(&bytes[i..], 8, 3);
+                i += len;
+                value as u8
+            }
+            b'0'..=b'7' if !octal_zero => {
+                let (value, len) = parse_digits(&bytes[i - 1..], 8, 3);
+                i += len - 1;
+                value as u8
+            }
+            _ => {
+                output.extend([b'\\', escape]);
+                continue;
+            }
+        };
+        output.push(byte);
+    }
+    true
+}
+
+// 先頭から最大max_len桁の数字を読み, 値と読んだ桁数を返す
+fn parse_digits(bytes: &[u8], radix: u32, max_len: usize) -> (u32, usize) {
+    let mut value = 0;
+    let mut len = 0;
+    for &b in bytes.iter().take(max_len) {
+        match char::from(b).to_digit(radix) {
+            Some(digit) => {
+                value = value * radix + digit;
+                len += 1;
+            }
+            None => break,
+        }
+    }
+    (value, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: &str, args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (output, errors) = printf(format, &args);
+        assert!(errors.is_empty(), "{:?}", errors);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_printf() {
+        assert_eq!(format("%s-%s\\n", &["a", "b", "c"]), "a-b\nc-\n");
+        assert_eq!(
+            format("[%5s|%-5s|%.2s]", &["ab", "cd", "xyz"]),
+            "[   ab|cd   |xy]"
+        );
+        assert_eq!(
+            format("%d %i %+d % d", &["42", "-7", "3", "3"]),
+            "42 -7 +3  3"
+        );
+        assert_eq!(
+            format("%05d|%-4d|%.3d", &["-42", "7", "5"]),
+            "-0042|7   |005"
+        );
+        assert_eq!(
+            format("%x %X %#x %o %#o", &["255", "255", "255", "8", "8"]),
+            "ff FF 0xff 10 010"
+        );
+        assert_eq!(format("%d %d %d", &["0x10", "010", "'A"]), "16 8 65");
+        assert_eq!(format("%u", &["-1"]), "18446744073709551615");
+        assert_eq!(format("%*d|%-*d|", &["4", "1", "3", "2"]), "   1|2  |");
+        assert_eq!(format("%c%c", &["hello", "world"]), "hw");
+        assert_eq!(format("%%%s%%", &["x"]), "%x%");
+        assert_eq!(format("%s %d\\n", &[]), " 0\n");
+        assert_eq!(format("no args\\t\\101\\x42", &["ignored"]), "no args\tAB");
+    }
+
+    #[test]
+    fn test_printf_float() {
+        assert_eq!(
+            format("%f %.2f %8.3f", &["1.5", "3.14159", "-2"]),
+            "1.500000 3.14   -2.000"
+        );
+        assert_eq!(
+            format("%e %.2E", &["1234.5", "0.000123"]),
+            "1.234500e+03 1.23E-04"
+        );
+        assert_eq!(
+            format("%g %g %g %G", &["100000", "1000000", "0.0001", "1e-5"]),
+            "100000 1e+06 0.0001 1E-05"
+        );
+        assert_eq!(
+            format("%g %#g %.3g", &["1.5", "1.5", "3.14159"]),
+            "1.5 1.50000 3.14"
+        );
+        assert_eq!(format("%05.1f|%+.0f", &["-1.25", "2.5"]), "-01.2|+2");
+    }
+
+    #[test]
+    fn test_printf_escapes() {
+        assert_eq!(format("%b|%s", &["a\\tb\\0101", "a\\tb"]), "a\tbA|a\\tb");
+        assert_eq!(format("a%bc", &["b\\cz"]), "ab");
+        assert_eq!(format("x\\cy", &[]), "x");
+
+        let (output, errors) = printf("%d %z", &["abc".to_string()]);
+        assert_eq!(output, b"0 ");
+        assert_eq!(
+            errors,
+            vec![
+                "abc: invalid number".to_string(),
+                "%z: invalid format character".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_printf_field_limit() {
+        // 大きすぎる幅や精度はメモリを確保する前にエラーにして, その変換だけ飛ばす
+        let args = ["1".to_string(), "2".to_string(), "3".to_string()];
+        let (output, errors) = printf("%99999999999999d|%.99999999999999d|%d\\n", &args);
+        assert_eq!(output, b"||3\n");
+        assert_eq!(
+            errors,
+            vec![
+                "99999999999999: invalid field width".to_string(),
+                "99999999999999: Numerical result out of range".to_string()
+            ]
+        );
+
+        let (output, errors) = printf("%*s|", &["-99999999999".to_string(), "x".to_string()]);
+        assert_eq!(output, b"|");
+        assert_eq!(
+            errors,
+            vec!["-99999999999: invalid field width".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_expand_escapes() {
+        let expand = |s: &str, octal_zero: bool| {
+            let mut output = Vec::new();
+            let finished = expand_escapes(s, octal_zero, &mut output);
+            (output, finished)
+        };
+        assert_eq!(expand("a\\nb\\\\\\q", true), (b"a\nb\\\\q".to_vec(), true));
+        assert_eq!(expand("\\0101\\101", true), (b"A\\101".to_vec(), true));
+        assert_eq!(expand("\\0101\\101", false), (b"\x081A".to_vec(), true));
+        assert_eq!(expand("\\xffz\\x", true), (b"\xffz\\x".to_vec(), true));
+        assert_eq!(expand("stop\\chere", true), (b"stop".to_vec(), false));
+        assert_eq!(expand("trailing\\", true), (b"trailing\\".to_vec(), true));
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    fs,
+    mem::MaybeUninit,
+    path::{Path, PathBuf},
+};
+
+use colored::*;
+use dirs::home_dir;
+use nix::{
+    libc,
+    unistd::{geteuid, gethostname, User},
+};
+
+use crate::{builtins, expand::abbreviate_home, state::ShellState};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// コマンドを読む時のプロンプト. PS1が設定されていればエスケープを展開したもの,
+// 設定されていなければカレントディレクトリと`$ `を表示する
+pub fn primary(state: &ShellState) -> String {
+    match state.var("PS1") {
+        Some(ps1) => expand_prompt(state, ps1),
+        None => {
+            let current_dir = current_dir(state);
+            format!("{} {}", current_dir.blue().bold(), "$ ".white())
+        }
+    }
+}
+
+// クォートが閉じていないなどで続きの行を読む時のプロンプト
+pub fn continuation(state: &ShellState) -> String {
+    match state.var("PS2") {
+        Some(ps2) => expand_prompt(state, ps2),
+        None => "> ".to_string(),
+    }
+}
+
+// bashのPS1と同じような\から始まるエスケープを展開する
+//   \u ユーザー名  \h ホスト名(最初の.まで)  \H ホスト名  \w カレントディレクトリ  \W その最後の部分
+//   \? 直前の終了ステータス  \g gitのブランチ名(リポジトリの外では空)  \$ rootなら#, それ以外は$
+//   \t 24時間表記のHH:MM:SS  \T 12時間表記のHH:MM:SS  \@ 12時間表記のHH:MM AM  \A 24時間表記のHH:MM
+//   \d `Tue May 26`の形式の日付  \j ジョブの数  \! 次のコマンドの履歴番号  \s シェルの名前
+//   \n 改行  \e ESC  \a ベル  \\ バックスラッシュ  \[ \] 表示されない文字列の範囲(読み捨てる)
+// 知らないエスケープはそのまま残す
+pub fn expand_prompt(state: &ShellState, ps: &str) -> String {
+    let mut result = String::new();
+    let mut chars = ps.chars();
+    // 時刻は複数のエスケープで使っても同じものになるように1回だけ取得する
+    let mut time = None;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            result.push('\\');
+            break;
+        };
+        match escape {
+            'u' => result.push_str(&user_name(state)),
+            'h' => result.push_str(host_name().split('.').next().unwrap_or_default()),
+            'H' => result.push_str(&host_name()),
+            'w' => result.push_str(&current_dir(state)),
+            'W' => result.push_str(&current_dir_name(state)),
+            '?' => result.push_str(&state.last_status.to_string()),
+            'g' => result.push_str(
+                &builtins::current_dir(state)
+                    .ok()
+                    .and_then(|dir| git_branch(Path::new(&dir)))
+                    .unwrap_or_default(),
+            ),
+            '$' => result.push(if geteuid().is_root() { '#' } else { '$' }),
+            't' | 'T' | '@' | 'A' | 'd' => {
+                let Some(time) = time.get_or_insert_with(local_time) else {
+                    continue;
+                };
+                result.push_str(&time.format(escape));
+            }
+            'j' => result.push_str(&state.jobs.ids().len().to_string()),
+            '!' => result.push_str(&(state.history.len() + 1).to_string()),
+            's' => result.push_str(shell_name(state)),
+            'n' => result.push('\n'),
+            'e' => result.push('\x1b'),
+            'a' => result.push('\x07'),
+            '\\' => result.push('\\'),
+            // rustylineはエスケープシーケンスの幅を自分で計算するので, 範囲の印は要らない
+            '[' | ']' => {}
+            c => {
+                result.push('\\');
+                result.push(c);
+            }
+        }
+    }
+    result
+}
+
+// $HOMEが設定されていればそちらを優先する. 先頭から一致する場合だけ~に置き換える
+fn home(state: &ShellState) -> Option<PathBuf> {
+    state.var("HOME").map(PathBuf::from).or_else(home_dir)
+}
+
+// カレントディレクトリが分からない場合は空にする
+fn current_dir(state: &ShellState) -> String {
+    let Ok(current_dir) = builtins::current_dir(state) else {
+        return String::new();
+    };
+    match home(state) {
+        Some(home) => abbreviate_home(Path::new(&current_dir), &home),
+        None => current_dir,
+    }
+}
+
+// ホームディレクトリ自体は~, それ以外はディレクトリ名だけにする
+fn current_dir_name(state: &ShellState) -> String {
+    let Ok(current_dir) = builtins::current_dir(state) else {
+        return String::new();
+    };
+    if home(state).is_some_and(|home| home == Path::new(&current_dir)) {
+        return "~".to_string();
+    }
+    match Path::new(&current_dir).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => current_dir,
+    }
+}
+
+fn user_name(state: &ShellState) -> String {
+    if let Some(user) = state.var("USER") {
+        return user.to_string();
+    }
+    match User::from_uid(geteuid()) {
+        Ok(Some(user)) => user.name,
+        _ => String::new(),
+    }
+}
+
+fn host_name() -> String {
+    gethostname()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// `$0`の最後の部分. スクリプトを実行している場合はスクリプトのファイル名になる
+fn shell_name(state: &ShellState) -> &str {
+    let name = state.script_name.as_str();
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+// dirから親ディレクトリを辿って.gitを探し, HEADが指しているブランチ名を返す
+// ブランチではなくコミットを直接指している場合はハッシュの先頭7文字を返す
+fn git_branch(dir: &Path) -> Option<String> {
+    let git_dir = dir.ancestors().find_map(|dir| {
+        let git = dir.join(".git");
+        if git.is_dir() {
+            return Some(git);
+        }
+        // worktreeやサブモジュールでは.gitが`gitdir: path`と書かれたファイルになっている
+        let content = fs::read_to_string(&git).ok()?;
+        let path = content.strip_prefix("gitdir:")?.trim();
+        Some(dir.join(path))
+    })?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(
+                reference
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(reference)
+                    .to_string(),
+            )
+        }
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+struct LocalTime {
+    // 0から始まる
+    month: usize,
+    day: i32,
+    // 日曜日が0
+    weekday: usize,
+    hour: i32,
+    minute: i32,
+    second: i32,
+}
+
+impl LocalTime {
+    fn format(&self, escape: char) -> String {
+        let hour12 = match self.hour % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        let meridiem = if self.hour < 12 { "AM" } else { "PM" };
+        match escape {
+            't' => format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second),
+            'T' => format!("{:02}:{:02}:{:02}", hour12, self.minute, self.second),
+            '@' => format!("{:02}:{:02} {}", hour12, self.minute, meridiem),
+            'A' => format!("{:02}:{:02}", self.hour, self.minute),
+            _ => format!(
+                "{} {} {:02}",
+                WEEKDAYS[self.weekday % 7],
+                MONTHS[self.month % 12],
+                self.day
+            ),
+        }
+    }
+}
+
+// タイムゾーンを考慮した現在時刻. 標準ライブラリには無いのでlocaltime_rを使う
+fn local_time() -> Option<LocalTime> {
+    let mut tm = MaybeUninit::<libc::tm>::zeroed();
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        if libc::localtime_r(&now, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        tm.assume_init()
+    };
+    Some(LocalTime {
+        month: tm.tm_mon as usize,
+        day: tm.tm_mday,
+        weekday: tm.tm_wday as usize,
+        hour: tm.tm_hour,
+        minute: tm.tm_min,
+        second: tm.tm_sec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_expand_prompt() {
+        let mut state = ShellState::default();
+        state.set_var("USER", "alice");
+        state.set_var("HOME", "/home/alice");
+        state.set_var("PWD", "/home/alice/src/shell");
+        state.last_status = 127;
+        state.script_name = "/usr/local/bin/shell".to_string();
+        let expand = |ps: &str| expand_prompt(&state, ps);
+
+        let prompt_char = if geteuid().is_root() { '#' } else { '$' };
+        assert_eq!(
+            expand("\\u:\\w \\W\\$ "),
+            format!("alice:~/src/shell shell{} ", prompt_char)
+        );
+        assert_eq!(expand("[\\?] \\s \\!"), "[127] shell 1");
+        assert_eq!(
+            expand("\\[\\e[1m\\]x\\[\\e[0m\\]\\n\\\\ \\q \\"),
+            "\x1b[1mx\x1b[0m\n\\ \\q \\"
+        );
+        assert_eq!(expand("\\h"), host_name().split('.').next().unwrap());
+        assert_eq!(expand("\\A").len(), 5);
+        assert_eq!(expand("\\t").len(), 8);
+        assert!(expand("\\@").ends_with('M'));
+
+        state.set_var("PWD", "/home/alice");
+        assert_eq!(expand_prompt(&state, "\\w \\W"), "~ ~");
+        state.set_var("PWD", "/");
+        assert_eq!(expand_prompt(&state, "\\w \\W"), "/ /");
+    }
+
+    #[test]
+    fn test_git_branch() {
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let repo = dir.join("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("src/deep")).unwrap();
+        fs::write(repo.join(".git/HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+
+        assert_eq!(
+            git_branch(&repo.join("src/deep")),
+            Some("feature/x".to_string())
+        );
+
+        // worktreeの.gitファイル
+        let worktree = dir.join("worktree");
+        fs::create_dir_all(dir.join("gitdir")).unwrap();
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(worktree.join(".git"), "gitdir: ../gitdir\n").unwrap();
+        fs::write(
+            dir.join("gitdir/HEAD"),
+            "0123456789abcdef0123456789abcdef01234567\n",
+        )
+        .unwrap();
+        assert_eq!(git_branch(&worktree), Some("0123456".to_string()));
+    }
+}
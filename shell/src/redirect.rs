@@ -0,0 +1,233 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    process::{self, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nix::unistd::dup2;
+
+use crate::{
+    ast::{Redirect, RedirectKind},
+    error::describe,
+    expand::expand_word,
+    state::ShellState,
+    token::Word,
+};
+
+// LinuxのPIPE_BUF. パイプの容量はデフォルトでは64KiBだが, pipe-user-pages-softを超えたユーザーでは
+// 1ページ(4096バイト)に縮められる. これ以下であればどちらでも読む側を待たずに書き込み終えられる
+const PIPE_BUF: usize = 4096;
+
+// 子プロセスに渡す標準入力, 標準出力, 標準エラー出力. Noneの場合はシェル自身のものをそのまま引き継ぐ
+#[derive(Debug, Default)]
+pub struct Streams {
+    pub stdin: Option<OwnedFd>,
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+    // パイプラインで次のコマンドの標準入力になるパイプの読み込み側. 外部コマンドにはCLOEXECで渡らないが,
+    // サブシェルはforkで持ったままになり, 読み手が終了しても書き込みがEPIPEにならないので子プロセスで閉じる
+    pub next_stdin: Option<RawFd>,
+}
+
+impl Streams {
+    fn slot(&mut self, fd: u32) -> io::Result<&mut Option<OwnedFd>> {
+        match fd {
+            0 => Ok(&mut self.stdin),
+            1 => Ok(&mut self.stdout),
+            2 => Ok(&mut self.stderr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: unsupported file descriptor", fd),
+            )),
+        }
+    }
+
+    // fdが現在指している先を複製する. まだ何もリダイレクトされていなければシェル自身のfdを複製する
+    fn duplicate(&mut self, fd: u32) -> io::Result<OwnedFd> {
+        match self.slot(fd)? {
+            Some(owned) => owned.try_clone(),
+            None => match fd {
+                0 => io::stdin().as_fd().try_clone_to_owned(),
+                1 => io::stdout().as_fd().try_clone_to_owned(),
+                _ => io::stderr().as_fd().try_clone_to_owned(),
+            },
+        }
+    }
+
+    pub fn into_stdio(self) -> (Stdio, Stdio, Stdio) {
+        let into_stdio = |fd: Option<OwnedFd>| fd.map_or(Stdio::inherit(), Stdio::from);
+        (
+            into_stdio(self.stdin),
+            into_stdio(self.stdout),
+            into_stdio(self.stderr),
+        )
+    }
+}
+
+// 差し替える前のシェル自身のfd. restoreで元に戻す
+#[derive(Debug, Default)]
+pub struct SavedFds(Vec<(RawFd, OwnedFd)>);
+
+impl SavedFds {
+    fn replace(&mut self, fd: RawFd, target: OwnedFd) -> io::Result<()> {
+        let original = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+        dup2(target.as_raw_fd(), fd)?;
+        self.0.push((fd, original));
+        Ok(())
+    }
+
+    pub fn restore(self) {
+        let _ = io::stdout().flush();
+        for (fd, original) in self.0.into_iter().rev() {
+            let _ = dup2(original.as_raw_fd(), fd);
+        }
+    }
+}
+
+// 子プロセスに渡すのではなく, シェル自身の0, 1, 2番のfdをstreamsの向き先に差し替える
+// `for ...; done > out`のような複合コマンドはシェルの中で実行するので, ビルトインの出力も含めてリダイレクトされる
+pub fn redirect_shell(streams: Streams) -> io::Result<SavedFds> {
+    // 差し替える前にバッファに残っている出力を元の出力先に吐き出しておく
+    let _ = io::stdout().flush();
+    let mut saved = SavedFds::default();
+    let targets = [(0, streams.stdin), (1, streams.stdout), (2, streams.stderr)];
+    for (fd, target) in targets {
+        let Some(target) = target else {
+            continue;
+        };
+        if let Err(e) = saved.replace(fd, target) {
+            saved.restore();
+            return Err(e);
+        }
+    }
+    Ok(saved)
+}
+
+// `2>&1 > file`と`> file 2>&1`で結果が変わるように, リダイレクトは左から順番に適用する
+pub fn apply_redirects(
+    state: &mut ShellState,
+    redirects: &[Redirect],
+    streams: &mut Streams,
+) -> io::Result<()> {
+    let expand = |state: &mut ShellState, word: &Word| {
+        expand_word(state, word)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+    };
+    for redirect in redirects {
+        // ヒアドキュメントの区切り文字は展開しない
+        if let Some(body) = &redirect.body {
+            let content = expand(state, body)?;
+            *streams.slot(redirect.fd)? = Some(content_pipe(content)?);
+            continue;
+        }
+        let target = expand(state, &redirect.target)?;
+        let with_target =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", target, describe(&e)));
+
+        match redirect.kind {
+            RedirectKind::Input => {
+                let file = File::open(&target).map_err(with_target)?;
+                *streams.slot(redirect.fd)? = Some(file.into());
+            }
+            RedirectKind::Output | RedirectKind::Append => {
+                let file = open_for_write(&target, redirect.kind == RedirectKind::Append)
+                    .map_err(with_target)?;
+                *streams.slot(redirect.fd)? = Some(file.into());
+            }
+            RedirectKind::OutputAll | RedirectKind::AppendAll => {
+                let file = open_for_write(&target, redirect.kind == RedirectKind::AppendAll)
+                    .map_err(with_target)?;
+                streams.stderr = Some(file.try_clone()?.into());
+                streams.stdout = Some(file.into());
+            }
+            RedirectKind::Duplicate | RedirectKind::DuplicateInput => {
+                let source = target.parse::<u32>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: ambiguous redirect", target),
+                    )
+                })?;
+                let duplicated = streams.duplicate(source)?;
+                *streams.slot(redirect.fd)? = Some(duplicated);
+            }
+            RedirectKind::HereString => {
+                *streams.slot(redirect.fd)? = Some(content_pipe(format!("{}\n", target))?);
+            }
+            // bodyが無いヒアドキュメントはパーサーが作らない
+            RedirectKind::HereDoc | RedirectKind::HereDocStripTabs => {}
+        }
+    }
+    Ok(())
+}
+
+// contentを読み出せるfdを返す. パイプの容量に収まればその場で書き込んで閉じたパイプの読み込み側を,
+// 収まらない場合は一時ファイルに書き込んで先頭に戻したものを返す
+// 書き込みを別スレッドなどで続けると, その間にforkしたサブシェルが書き込み側を持ったままになって読む側がEOFにならない
+fn content_pipe(content: String) -> io::Result<OwnedFd> {
+    if content.len() > PIPE_BUF {
+        let mut file = temp_file()?;
+        file.write_all(content.as_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(file.into());
+    }
+    let (reader, mut writer) = io::pipe()?;
+    writer.write_all(content.as_bytes())?;
+    Ok(reader.into())
+}
+
+// 他のプロセスと名前がぶつからない一時ファイルを作り, 開いたまま削除しておく. 閉じれば中身も消える
+fn temp_file() -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("shell-{}-{}", process::id(), count));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn open_for_write(path: &str, append: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use nix::sys::stat::{fstat, SFlag};
+
+    use super::*;
+
+    #[test]
+    fn test_content_pipe() {
+        // 1ページに縮められたパイプにも書き込めない大きさは, 途中までのヒアドキュメントでも一時ファイルにする
+        for (len, kind) in [(10, SFlag::S_IFIFO), (5000, SFlag::S_IFREG)] {
+            let fd = content_pipe("x".repeat(len)).unwrap();
+            let mode = fstat(fd.as_raw_fd()).unwrap().st_mode;
+            assert_eq!(SFlag::from_bits_truncate(mode) & SFlag::S_IFMT, kind);
+
+            let mut content = String::new();
+            File::from(fd).read_to_string(&mut content).unwrap();
+            assert_eq!(content.len(), len);
+        }
+    }
+}
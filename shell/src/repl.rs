@@ -0,0 +1,221 @@
+use std::{fs::File, io, io::BufReader, path::PathBuf};
+
+use dirs::home_dir;
+
+use crate::{
+    ast::List,
+    error::{describe, ShellError},
+    executor::{execute_list, Executor},
+    history,
+    input::Input,
+    job,
+    lexer::LexError,
+    parser::{self, ParseError},
+    prompt,
+    state::ShellState,
+};
+
+// 対話モードの起動時に読み込むホームディレクトリの設定ファイル
+const RC_FILE: &str = ".shellrc";
+
+// inputからコマンドを読んでは実行することを, EOFかexitまで繰り返す. シェル自体の終了ステータスを返す
+// 対話モードの場合はジョブ制御を有効にして, 最初に設定ファイルを読み込む
+pub fn run(executor: &mut Executor, input: &mut Input) -> i32 {
+    executor.state.interactive = input.is_interactive();
+    if input.is_interactive() {
+        executor.state.terminal = job::init_job_control();
+        load_rc_file(executor);
+    }
+
+    while executor.state.exit_status.is_none() {
+        // バックグラウンドで実行していたジョブが終了していれば, プロンプトを表示する前に知らせる
+        for notification in executor.state.jobs.reap() {
+            eprintln!("{}", notification);
+        }
+
+        let prompt = if input.is_interactive() {
+            prompt::primary(&executor.state)
+        } else {
+            String::new()
+        };
+
+        match read_list(input, &mut executor.state, &prompt) {
+            Ok(Some(list)) => execute(executor, &list),
+            Ok(None) => (),
+            // 対話モードではbashと同じくexitと表示してから終了する
+            Err(ShellError::Eof) => {
+                if input.is_interactive() {
+                    eprintln!("exit");
+                }
+                return executor.state.last_status;
+            }
+            // 対話モードの構文エラーは表示するだけで次の入力に進む
+            Err(e @ ShellError::Syntax(_)) if input.is_interactive() => {
+                eprintln!("shell: {}", e);
+                executor.state.last_status = e.status();
+            }
+            // スクリプトの場合は構文エラーがあればそこで終了する. 入力を読めなくなった場合も同じ
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                return e.status();
+            }
+        }
+    }
+    executor
+        .state
+        .exit_status
+        .unwrap_or(executor.state.last_status)
+}
+
+fn execute(executor: &mut Executor, list: &List) {
+    if let Err(e) = executor.execute(list) {
+        eprintln!("shell: {}", e);
+        executor.state.last_status = e.status();
+    }
+}
+
+// 対話モードの起動時に$HOME/.shellrcを実行する. $SHELLRCが設定されていればそちらを読む
+// 前の行で定義したエイリアスを後ろの行で使えるように, スクリプトと同じく1つずつ読んで実行する
+fn load_rc_file(executor: &mut Executor) {
+    let state = &executor.state;
+    let (path, explicit) = match state.var("SHELLRC") {
+        Some(path) => (PathBuf::from(path), true),
+        None => match state.var("HOME").map(PathBuf::from).or_else(home_dir) {
+            Some(home) => (home.join(RC_FILE), false),
+            None => return,
+        },
+    };
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        // デフォルトの場所に無いのは普通なので何も言わない
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => return,
+        Err(e) => {
+            eprintln!("shell: {}: {}", path.display(), describe(&e));
+            return;
+        }
+    };
+
+    let mut input = Input::from_reader(Box::new(BufReader::new(file)));
+    while executor.state.exit_status.is_none() {
+        match read_list(&mut input, &mut executor.state, "") {
+            Ok(Some(list)) => execute(executor, &list),
+            Ok(None) => (),
+            Err(ShellError::Eof) => break,
+            Err(e) => {
+                eprintln!("shell: {}: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+}
+
+// inputから読んだコマンドを今のシェルの中で順に実行する. sourceで使う
+// 入力の終わりかexit, returnまで実行し, 構文エラーがあればそこで止めてエラーを返す
+pub fn execute_input(state: &mut ShellState, input: &mut Input) -> Result<(), ShellError> {
+    while !state.is_unwinding() {
+        match read_list(input, state, "") {
+            Ok(Some(list)) => execute_list(state, &list),
+            Ok(None) => (),
+            Err(ShellError::Eof) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// 1行読んでパースする. クォートが閉じていない, 行末が"|"や"\"で終わっているなどの場合は続きの行を読み足す
+// 空行やコメントだけの行, Ctrl-Cで入力を中断した場合はNoneを返す. 何も読まずに入力が終わった場合はShellError::Eof
+// 読み終わった入力は, パースに失敗した場合も含めて履歴に追加する
+fn read_list(
+    input: &mut Input,
+    state: &mut ShellState,
+    prompt: &str,
+) -> Result<Option<List>, ShellError> {
+    let mut source = String::new();
+    if !read_line(input, state, prompt, &mut source)? {
+        return Ok(None);
+    }
+
+    let result = loop {
+        match parser::parse_with_aliases(&source, &state.aliases) {
+            Err(e) if e.is_incomplete() => {
+                match read_continuation(input, state, &e, &mut source) {
+                    Ok(true) => (),
+                    Ok(false) => return Ok(None),
+                    // 続きを読む前に入力が終わった場合は, 閉じていない構文のエラーにする
+                    Err(ShellError::Eof) => break Err(e.into()),
+                    Err(e) => return Err(e),
+                }
+            }
+            result => break result.map_err(ShellError::from),
+        }
+    };
+    input.add_history(state, source.trim_end_matches('\n'));
+    result
+}
+
+// 足りない続きの行をsourceに読み足す. ヒアドキュメントの本文や閉じていないクォートの途中の行では
+// パースし直しても結果が変わらないので, 終わりになりうる行を読むまでまとめて読む
+// 長いヒアドキュメントで1行ごとに全体をパースし直すと, 行数の2乗に比例して遅くなる
+fn read_continuation(
+    input: &mut Input,
+    state: &ShellState,
+    error: &ParseError,
+    source: &mut String,
+) -> Result<bool, ShellError> {
+    let prompt = prompt::continuation(state);
+    loop {
+        let start = source.len();
+        if !read_line(input, state, &prompt, source)? {
+            return Ok(false);
+        }
+        let line = &source[start..];
+        let may_complete = match error {
+            ParseError::Lex(LexError::UnterminatedHereDoc(delimiter)) => {
+                line.trim_start_matches('\t').trim_end_matches('\n') == delimiter
+            }
+            ParseError::Lex(LexError::UnterminatedQuote(quote)) => line.contains(*quote),
+            _ => true,
+        };
+        if may_complete {
+            return Ok(true);
+        }
+    }
+}
+
+// 1行読んでsourceに追加する. 入力が終わっていればShellError::Eofを返す
+// Ctrl-Cで中断された場合は改行して新しいプロンプトを出せるようにfalseを返す
+// 対話モードでは`!!`などの履歴の参照を展開し, 展開した場合は展開後のコマンドを表示する
+fn read_line(
+    input: &mut Input,
+    state: &ShellState,
+    prompt: &str,
+    source: &mut String,
+) -> Result<bool, ShellError> {
+    let mut line = String::new();
+    match input.read_line(state, prompt, &mut line) {
+        Ok(0) => return Err(ShellError::Eof),
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+            println!();
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    if input.is_interactive() {
+        match history::expand_history(&line, &state.history) {
+            Ok(Some(expanded)) => {
+                print!("{}", expanded);
+                line = expanded;
+            }
+            Ok(None) => (),
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                return Ok(false);
+            }
+        }
+    }
+    source.push_str(&line);
+    Ok(true)
+}
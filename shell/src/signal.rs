@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::sys::signal::{sigaction, signal, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+// 対話モードのシェル自身は無視しておくシグナル. これらはフォアグラウンドのジョブにだけ効けばよい
+// SIGTSTP(Ctrl-Z)とSIGQUIT(Ctrl-\)はシェルが止まったり落ちたりしないように,
+// SIGTTOUとSIGTTINはバックグラウンドからtcsetpgrpで端末を取り戻す時にシェルが停止しないように無視する
+const IGNORED_SIGNALS: [Signal; 4] = [
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTOU,
+    Signal::SIGTTIN,
+];
+
+// プロンプトで入力中にCtrl-Cが押されたかどうか
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// SIGINTは無視するのではなくハンドラを設定する. SA_RESTARTを付けないことで,
+// 入力待ちのreadがEINTRで中断されて入力中の行を破棄できるようにする
+pub fn init_interactive_signals() {
+    for sig in IGNORED_SIGNALS {
+        // SigIgnを設定するだけなのでハンドラの安全性の問題はない
+        let _ = unsafe { signal(sig, SigHandler::SigIgn) };
+    }
+
+    let action = SigAction::new(
+        SigHandler::Handler(handle_sigint),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    // ハンドラはAtomicBoolを書き換えるだけなのでasync-signal-safe
+    let _ = unsafe { sigaction(Signal::SIGINT, &action) };
+}
+
+// Ctrl-Cが押されていればフラグを下ろしてtrueを返す
+pub fn take_interrupted() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+// 無視する設定はexecしても子プロセスに引き継がれてしまうので, 子プロセスではデフォルトに戻す
+// SIGINTのハンドラはexecすれば元に戻るが, execせずにコマンドを実行するサブシェルのために明示的に戻す
+// fork後exec前の子プロセスから呼ばれるので, async-signal-safeな処理だけを行うこと
+pub fn restore_default_signals() -> nix::Result<()> {
+    for sig in IGNORED_SIGNALS.iter().chain([Signal::SIGINT].iter()) {
+        unsafe { signal(*sig, SigHandler::SigDfl) }?;
+    }
+    Ok(())
+}
+
+// Rustのプログラムは起動時にSIGPIPEを無視するようになっている. 外部コマンドはspawnの時に戻されるが,
+// サブシェルはforkしただけなので自分で戻し, 読み手のいなくなったパイプに書いたら外部コマンドと同じく静かに終了する
+pub fn restore_sigpipe() {
+    // SigDflを設定するだけなのでハンドラの安全性の問題はない
+    let _ = unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl) };
+}
@@ -0,0 +1,219 @@
+use std::{collections::HashMap, env, fs, path::Path, process};
+
+use nix::unistd::Pid;
+
+use crate::{
+    ast::FunctionDef,
+    error::ShellError,
+    job::{JobTable, Terminal},
+};
+
+// コマンドの実行をまたいで保持しておくシェル自身の状態
+#[derive(Debug, Default)]
+pub struct ShellState {
+    // 直前に実行したパイプラインの終了ステータス. `$?`で参照できる
+    pub last_status: i32,
+    // 直前に実行したパイプラインの各コマンドの終了ステータス. `$PIPESTATUS`で参照できる
+    pub pipestatus: Vec<i32>,
+    pub options: Options,
+    pub jobs: JobTable,
+    // ジョブ制御が有効な場合(標準入力が端末の場合)だけSome
+    pub terminal: Option<Terminal>,
+    // 対話モードで実行している場合にtrue. スクリプトでは`${X:?}`のエラーでシェル自体を終了する
+    pub interactive: bool,
+    // exitが実行された場合にSomeになる. 中身はシェル自体の終了ステータス
+    pub exit_status: Option<i32>,
+    // シェル変数. exportされたものだけが子プロセスに環境変数として渡される
+    pub vars: HashMap<String, Variable>,
+    // `$$`. サブシェルの中でも元のシェルのプロセスIDを返す
+    pub shell_pid: u32,
+    // `$!`. 最後にバックグラウンドで起動したプロセスのID
+    pub last_background_pid: Option<Pid>,
+    // pushdで積んだディレクトリ. 最後の要素がスタックの一番上
+    pub dir_stack: Vec<String>,
+    // 対話モードで入力したコマンドの履歴. 古いものから順に並んでいる
+    pub history: Vec<String>,
+    // `$0`. シェルスクリプトを実行している場合はそのファイル名
+    pub script_name: String,
+    // `$1`, `$2`, ...
+    pub positional: Vec<String>,
+    // breakやcontinueが実行された場合にSomeになる. 対応するループまで実行中のリストを打ち切って戻る
+    pub flow: Option<Flow>,
+    // 実行中のループの深さ. `break 5`のように実際より多い段数が指定された場合は一番外側のループを抜ける
+    pub loop_depth: usize,
+    // `name() { ... }`で定義したシェル関数
+    pub functions: HashMap<String, FunctionDef>,
+    // 実行中の関数呼び出しごとに, localで上書きした変数の元の値を覚えておく. 関数から戻る時に元に戻す
+    pub local_scopes: Vec<Vec<(String, Option<Variable>)>>,
+    // aliasで定義したエイリアス. 名前と置き換える文字列
+    pub aliases: HashMap<String, String>,
+    // sourceで実行中のファイルの深さ. 0でなければreturnでファイルの残りを飛ばせる
+    pub source_depth: usize,
+}
+
+// Break, Continueの中身は抜けるループの段数. `break 2`なら2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    // returnで関数から戻る途中
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+impl ShellState {
+    // 起動時の環境変数は全てexport済みのシェル変数として引き継ぐ
+    pub fn new() -> Self {
+        let vars = env::vars()
+            .map(|(name, value)| {
+                let var = Variable {
+                    value,
+                    exported: true,
+                };
+                (name, var)
+            })
+            .collect();
+        let mut state = ShellState {
+            vars,
+            shell_pid: process::id(),
+            ..Default::default()
+        };
+        // 引き継いだPWDが実際のカレントディレクトリと違う場合は信用しない
+        match env::current_dir() {
+            Ok(cwd) => {
+                let same_dir = state.var("PWD").is_some_and(|pwd| {
+                    Path::new(pwd).is_absolute()
+                        && fs::canonicalize(pwd).ok() == fs::canonicalize(&cwd).ok()
+                });
+                if !same_dir {
+                    state.export_var("PWD", Some(&cwd.display().to_string()));
+                }
+            }
+            // 起動したディレクトリが既に削除されている場合. bashと同じく警告だけ出して続ける
+            Err(e) => eprintln!("shell-init: {}", ShellError::CurrentDir(e)),
+        }
+        state
+    }
+
+    // exitやbreakによって, 実行中のコマンドの残りを飛ばす必要があるかどうか
+    pub fn is_unwinding(&self) -> bool {
+        self.exit_status.is_some() || self.flow.is_some()
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|var| var.value.as_str())
+    }
+
+    // 既にexportされている変数に代入した場合はexportされたままになる
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        match self.vars.get_mut(name) {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                let var = Variable {
+                    value: value.to_string(),
+                    exported: false,
+                };
+                self.vars.insert(name.to_string(), var);
+            }
+        }
+    }
+
+    // `export NAME`のように値が無い場合は, 既存の値(無ければ空文字列)をそのままexportする
+    pub fn export_var(&mut self, name: &str, value: Option<&str>) {
+        let var = self.vars.entry(name.to_string()).or_insert(Variable {
+            value: String::new(),
+            exported: true,
+        });
+        var.exported = true;
+        if let Some(value) = value {
+            var.value = value.to_string();
+        }
+    }
+
+    pub fn unexport_var(&mut self, name: &str) {
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = false;
+        }
+    }
+
+    pub fn unset_var(&mut self, name: &str) -> Option<Variable> {
+        self.vars.remove(name)
+    }
+
+    // 関数の中で`local name`した変数. 最初の1回だけ元の値を覚えておき, 変数は一旦未設定にする
+    pub fn declare_local(&mut self, name: &str) {
+        let Some(scope) = self.local_scopes.last_mut() else {
+            return;
+        };
+        if !scope.iter().any(|(local, _)| local == name) {
+            let original = self.vars.remove(name);
+            scope.push((name.to_string(), original));
+        }
+    }
+
+    pub fn push_local_scope(&mut self) {
+        self.local_scopes.push(Vec::new());
+    }
+
+    // localで上書きした変数を関数を呼ぶ前の状態に戻す
+    pub fn pop_local_scope(&mut self) {
+        let Some(scope) = self.local_scopes.pop() else {
+            return;
+        };
+        for (name, original) in scope.into_iter().rev() {
+            match original {
+                Some(var) => self.vars.insert(name, var),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+
+    // 子プロセスに渡す環境変数. 表示にも使うので名前順に並べておく
+    pub fn exported_vars(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .vars
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.clone(), var.value.clone()))
+            .collect();
+        vars.sort();
+        vars
+    }
+}
+
+// 変数名として使える文字列かどうか. 英字か_で始まり, 英数字と_だけからなる
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+            chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        }
+        _ => false,
+    }
+}
+
+// `set -o`で切り替えられるオプション
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    // パイプラインの途中のコマンドが失敗した場合もパイプライン全体を失敗扱いにする
+    pub pipefail: bool,
+}
+
+impl Options {
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match name {
+            "pipefail" => self.pipefail = value,
+            _ => return Err(format!("{}: invalid option name", name)),
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![("pipefail", self.pipefail)]
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Word(Word),
+    Pipe,
+    // &&
+    AndIf,
+    // ||
+    OrIf,
+    Semi,
+    // ;;. caseの各パターンの終わり
+    DSemi,
+    LParen,
+    RParen,
+    // &. 末尾に付けたコマンドはバックグラウンドで実行する
+    Amp,
+    Newline,
+    // `2>`の2のようにリダイレクト演算子の直前に書かれたfd番号も一緒に持つ
+    Redirect(Option<u32>, RedirectOp),
+    // <<の後ろの区切り文字と, 次の行から区切り文字だけの行までを読んだ本文
+    HereDoc { delimiter: Word, body: Word },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RedirectOp {
+    // <
+    Less,
+    // >
+    Great,
+    // >>
+    DGreat,
+    // <&
+    LessAnd,
+    // >&
+    GreatAnd,
+    // &>
+    AndGreat,
+    // &>>
+    AndDGreat,
+    // <<
+    DLess,
+    // <<-. ヒアドキュメントの各行の先頭のタブを取り除く
+    DLessDash,
+    // <<<
+    TLess,
+}
+
+// クォートの情報を後段(展開処理など)で使えるように, 単語は文字列ではなくパーツの列として持つ
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum WordPart {
+    // クォートされていない文字列
+    Literal(String),
+    // シングルクォートやバックスラッシュでエスケープされた文字列. 中身は一切解釈しない
+    Quoted(String),
+    // ダブルクォートの中身
+    DoubleQuoted(Vec<WordPart>),
+    // $HOME, ${HOME:-/}, $? のようなパラメータ. 実行時に展開される
+    Param(ParamExpansion),
+    // $(...)や`...`. 中身のコマンドは実行する時にパースする
+    CommandSubst(String),
+    // $((...)). 中身を展開してから整数の式として評価する
+    Arith(Vec<WordPart>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParamExpansion {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParamOp {
+    // $name, ${name}
+    Plain,
+    // ${#name}. 値の文字数
+    Length,
+    // ${name:-word}. 未設定ならwordを使う
+    // 以下のboolは:が付いているかどうかで, 付いている場合は空文字列も未設定と同じように扱う
+    Default(bool, Word),
+    // ${name:=word}. 未設定ならwordを代入した上で使う
+    Assign(bool, Word),
+    // ${name:+word}. 設定されていればwordを使う
+    Alternative(bool, Word),
+    // ${name:?word}. 未設定ならwordをエラーメッセージとして表示して失敗する
+    Error(bool, Word),
+}
+
+impl ParamExpansion {
+    pub fn plain(name: &str) -> Self {
+        ParamExpansion {
+            name: name.to_string(),
+            op: ParamOp::Plain,
+        }
+    }
+}
+
+impl Word {
+    pub fn new(parts: Vec<WordPart>) -> Self {
+        Word { parts }
+    }
+
+    // クォートや展開を一切含まない単語であればその文字列を返す. 予約語の判定に使う
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(s)] => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn has_command_subst(&self) -> bool {
+        has_command_subst(&self.parts)
+    }
+
+    // クォートを取り除いた文字列を返す
+    pub fn unquote(&self) -> String {
+        unquote_parts(&self.parts)
+    }
+}
+
+fn unquote_parts(parts: &[WordPart]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            WordPart::Literal(s) | WordPart::Quoted(s) => s.clone(),
+            WordPart::DoubleQuoted(parts) => unquote_parts(parts),
+            WordPart::Param(param) => param.to_string(),
+            WordPart::CommandSubst(source) => format!("$({})", source),
+            WordPart::Arith(parts) => format!("$(({}))", unquote_parts(parts)),
+        })
+        .collect()
+}
+
+fn has_command_subst(parts: &[WordPart]) -> bool {
+    parts.iter().any(|part| match part {
+        WordPart::Literal(_) | WordPart::Quoted(_) => false,
+        WordPart::DoubleQuoted(parts) | WordPart::Arith(parts) => has_command_subst(parts),
+        WordPart::Param(param) => match &param.op {
+            ParamOp::Plain | ParamOp::Length => false,
+            ParamOp::Default(_, word)
+            | ParamOp::Assign(_, word)
+            | ParamOp::Alternative(_, word)
+            | ParamOp::Error(_, word) => word.has_command_subst(),
+        },
+        WordPart::CommandSubst(_) => true,
+    })
+}
+
+impl Display for WordPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WordPart::Literal(s) => write!(f, "{}", s),
+            WordPart::Quoted(s) => write!(f, "'{}'", s.replace('\'', r"'\''")),
+            WordPart::DoubleQuoted(parts) => {
+                write!(f, "\"")?;
+                fmt_parts(parts, f)?;
+                write!(f, "\"")
+            }
+            WordPart::Param(param) => write!(f, "{}", param),
+            WordPart::CommandSubst(source) => write!(f, "$({})", source),
+            WordPart::Arith(parts) => {
+                write!(f, "$((")?;
+                fmt_parts(parts, f)?;
+                write!(f, "))")
+            }
+        }
+    }
+}
+
+// `$x`の直後に英数字が続く場合は`${x}y`のように括弧を付けないと別の変数名になってしまう
+fn fmt_parts(parts: &[WordPart], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut parts = parts.iter().peekable();
+    while let Some(part) = parts.next() {
+        let next = parts.peek().map(|next| next.to_string());
+        let needs_brace = next
+            .is_some_and(|next| next.starts_with(|c: char| c == '_' || c.is_ascii_alphanumeric()));
+        match part {
+            WordPart::Param(param) if param.op == ParamOp::Plain && needs_brace => {
+                write!(f, "${{{}}}", param.name)?
+            }
+            part => write!(f, "{}", part)?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for ParamExpansion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (colon, op, word) = match &self.op {
+            ParamOp::Plain => return write!(f, "${}", self.name),
+            ParamOp::Length => return write!(f, "${{#{}}}", self.name),
+            ParamOp::Default(colon, word) => (colon, '-', word),
+            ParamOp::Assign(colon, word) => (colon, '=', word),
+            ParamOp::Alternative(colon, word) => (colon, '+', word),
+            ParamOp::Error(colon, word) => (colon, '?', word),
+        };
+        let colon = if *colon { ":" } else { "" };
+        write!(f, "${{{}{}{}{}}}", self.name, colon, op, word)
+    }
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_parts(&self.parts, f)
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Pipe => write!(f, "|"),
+            Token::AndIf => write!(f, "&&"),
+            Token::OrIf => write!(f, "||"),
+            Token::Semi => write!(f, ";"),
+            Token::DSemi => write!(f, ";;"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Amp => write!(f, "&"),
+            // エラーメッセージで"\n"をそのまま出すと読みにくいのでbashに合わせてnewlineと表示する
+            Token::Newline => write!(f, "newline"),
+            Token::Redirect(fd, op) => {
+                if let Some(fd) = fd {
+                    write!(f, "{}", fd)?;
+                }
+                write!(f, "{}", op)
+            }
+            Token::HereDoc { delimiter, .. } => write!(f, "{}", delimiter),
+        }
+    }
+}
+
+impl Display for RedirectOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            RedirectOp::Less => "<",
+            RedirectOp::Great => ">",
+            RedirectOp::DGreat => ">>",
+            RedirectOp::LessAnd => "<&",
+            RedirectOp::GreatAnd => ">&",
+            RedirectOp::AndGreat => "&>",
+            RedirectOp::AndDGreat => "&>>",
+            RedirectOp::DLess => "<<",
+            RedirectOp::DLessDash => "<<-",
+            RedirectOp::TLess => "<<<",
+        };
+        write!(f, "{}", op)
+    }
+}
@@ -0,0 +1,100 @@
+// ライブラリとしてパーサーとExecutorを使う
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, Write},
+    sync::Mutex,
+};
+
+use shell::{
+    ast::{Command, CompoundCommand, Connector},
+    parse, Executor, ShellError, ShellState,
+};
+use tempfile::TempDir;
+
+// Executorは実行中にdup2でプロセス全体の標準入出力を差し替えるので, 並列に走るテスト同士で同時に実行しないようにする
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn read_all(file: &mut File) -> String {
+    let mut content = String::new();
+    file.rewind().unwrap();
+    file.read_to_string(&mut content).unwrap();
+    content
+}
+
+#[test]
+fn test_parse_ast() {
+    let list = parse("ls -l | wc -l && echo ok &\nfor x in a b; do echo $x; done")
+        .unwrap()
+        .unwrap();
+    assert_eq!(list.and_ors.len(), 2);
+
+    let and_or = &list.and_ors[0];
+    assert!(and_or.background);
+    assert_eq!(and_or.first.commands.len(), 2);
+    let Command::Simple(ls) = &and_or.first.commands[0] else {
+        panic!("not a simple command");
+    };
+    let words: Vec<String> = ls.words.iter().map(|word| word.to_string()).collect();
+    assert_eq!(words, vec!["ls", "-l"]);
+    assert_eq!(and_or.rest[0].0, Connector::And);
+
+    let Command::Compound(CompoundCommand::For { name, .. }, _) =
+        &list.and_ors[1].first.commands[0]
+    else {
+        panic!("not a for loop");
+    };
+    assert_eq!(name, "x");
+
+    assert_eq!(parse("  # comment only\n"), Ok(None));
+    assert!(parse("if true; then").unwrap_err().is_incomplete());
+}
+
+#[test]
+fn test_executor_stdio() {
+    let _lock = LOCK.lock().unwrap();
+    let mut stdin = tempfile::tempfile().unwrap();
+    stdin.write_all(b"hello\nworld\n").unwrap();
+    stdin.rewind().unwrap();
+    let mut stdout = tempfile::tempfile().unwrap();
+    let mut stderr = tempfile::tempfile().unwrap();
+
+    let mut executor = Executor::new(ShellState::new())
+        .stdin(stdin)
+        .stdout(stdout.try_clone().unwrap())
+        .stderr(stderr.try_clone().unwrap());
+    let status = executor
+        .run("tr a-z A-Z | sort -r; ls /nonexistent")
+        .unwrap();
+    assert_ne!(status, 0);
+    assert_eq!(read_all(&mut stdout), "WORLD\nHELLO\n");
+    assert!(read_all(&mut stderr).contains("nonexistent"));
+}
+
+#[test]
+fn test_executor_state() {
+    let _lock = LOCK.lock().unwrap();
+    let dir = TempDir::new().unwrap();
+    let mut stdout = tempfile::tempfile().unwrap();
+    let mut executor = Executor::new(ShellState::new()).stdout(stdout.try_clone().unwrap());
+
+    // 状態は実行をまたいで引き継がれる
+    let dir_path = dir.path().display().to_string();
+    executor
+        .run(&format!(
+            "dir='{}'; n=0; add() {{ n=$((n + $1)); }}",
+            dir_path
+        ))
+        .unwrap();
+    executor
+        .run("for i in 1 2 3; do add $i; done; printf '%s\\n' $n > \"$dir/n.txt\"")
+        .unwrap();
+    assert_eq!(executor.state.var("n"), Some("6"));
+    assert_eq!(fs::read_to_string(dir.path().join("n.txt")).unwrap(), "6\n");
+
+    executor.run("printf '%s' $(ls \"$dir\")").unwrap();
+    assert_eq!(read_all(&mut stdout), "n.txt");
+
+    assert!(matches!(executor.run("echo )"), Err(ShellError::Syntax(_))));
+    assert_eq!(executor.run("exit 7").unwrap(), 7);
+    assert_eq!(executor.state.exit_status, Some(7));
+}
@@ -0,0 +1,401 @@
+// ビルドしたシェルのバイナリで一時ディレクトリの中のスクリプトを実行し, 出力と終了ステータスを確かめる
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use tempfile::TempDir;
+
+struct Output {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+// dirの中にscript.shとしてscriptを書き込み, dirをカレントディレクトリにして実行する
+fn run_in(dir: &Path, script: &str, args: &[&str]) -> Output {
+    fs::write(dir.join("script.sh"), script).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .arg("script.sh")
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env_remove("PWD")
+        .output()
+        .unwrap();
+    Output {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().unwrap_or(-1),
+    }
+}
+
+fn run(script: &str) -> Output {
+    let dir = TempDir::new().unwrap();
+    run_in(dir.path(), script, &[])
+}
+
+#[test]
+fn test_status_and_lists() {
+    let output =
+        run("false || echo or\ntrue && echo and\n! true; echo $?\nexit 3\necho unreachable\n");
+    assert_eq!(output.stdout, "or\nand\n1\n");
+    assert_eq!(output.status, 3);
+
+    // パイプラインの終了ステータスは最後のコマンドのもので, pipefailなら失敗した一番右のもの
+    // 最後のコマンドが先に終わっても, 全てのコマンドの終了を待ってから次に進む
+    let output = run("\
+false | true; echo $?
+(exit 3) | false | true; echo $PIPESTATUS
+set -o pipefail
+false | true; echo $?
+(exit 3) | false | true; echo $?
+true | true; echo $?
+set +o pipefail
+{ sleep 0.2; echo late > late.txt; } | true; cat late.txt
+");
+    assert_eq!(output.stdout, "0\n3 1 0\n1\n1\n0\nlate\n");
+
+    // 構文エラーがあるとそこで終了する
+    let output = run("echo before\nif then\necho after\n");
+    assert_eq!(output.stdout, "before\n");
+    assert!(output.stderr.contains("syntax error"));
+    assert_eq!(output.status, 2);
+}
+
+#[test]
+fn test_errors() {
+    let output = run("nosuchcommand\necho $?\n./script.sh\necho $?\nls | | wc\necho unreachable\n");
+    assert_eq!(output.stdout, "127\n126\n");
+    assert!(output.stderr.contains("nosuchcommand: command not found"));
+    assert!(output
+        .stderr
+        .contains("syntax error near unexpected token `|'"));
+    assert_eq!(output.status, 2);
+
+    // 閉じていない構文のまま入力が終わった
+    let output = run("echo start\nif true; then\n  echo never\n");
+    assert_eq!(output.stdout, "start\n");
+    assert!(output.stderr.contains("unexpected end of file"));
+    assert_eq!(output.status, 2);
+
+    // OSのエラーは他のビルトインと同じ形で表示し, `(os error 2)`のような番号は付けない
+    let output = run("cd nope\necho x > nope/file\ncat < nope\nsource nope\n");
+    assert_eq!(
+        output.stderr,
+        "\
+cd: nope: No such file or directory
+shell: nope/file: No such file or directory
+shell: nope: No such file or directory
+source: nope: No such file or directory
+"
+    );
+}
+
+#[test]
+fn test_positional_params() {
+    let dir = TempDir::new().unwrap();
+    let output = run_in(
+        dir.path(),
+        "echo $# \"$1\" $0\nfor arg; do echo \"[$arg]\"; done\nshift; echo \"$@\"\n",
+        &["a b", "c"],
+    );
+    assert_eq!(output.stdout, "2 a b script.sh\n[a b]\n[c]\nc\n");
+
+    // setで位置パラメータを置き換える. 関数の中では関数の引数だけが変わる
+    let output = run("\
+set -- x 'y z'
+echo $# \"$2\"
+f() { set a b c; echo $# $3; }
+f 1
+echo \"$@\"
+set --
+echo $#
+");
+    assert_eq!(output.stdout, "2 y z\n3 c\nx y z\n0\n");
+}
+
+#[test]
+fn test_redirects() {
+    let dir = TempDir::new().unwrap();
+    let script = "\
+echo first > out.txt
+echo second >> out.txt
+ls missing 2> err.txt
+wc -l < out.txt
+mkdir sub && cd sub && ls .. > ../list.txt
+";
+    let output = run_in(dir.path(), script, &[]);
+    assert_eq!(output.stdout.trim(), "2");
+    let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!(read("out.txt"), "first\nsecond\n");
+    assert!(read("err.txt").contains("missing"));
+    assert!(read("list.txt").contains("sub\n"));
+}
+
+#[test]
+fn test_loops() {
+    let script = "\
+i=0
+while [ $i -lt 3 ]; do
+    i=$((i + 1))
+    if [ $i -eq 2 ]; then continue; fi
+    echo while $i
+done
+until false; do echo until; break; done
+for x in a b c; do
+    for y in 1 2; do
+        [ $x = b ] && break 2
+        echo $x$y
+    done
+done
+for f in *.txt; do echo file $f; done
+";
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.txt"), "").unwrap();
+    fs::write(dir.path().join("b.txt"), "").unwrap();
+    let output = run_in(dir.path(), script, &[]);
+    assert_eq!(
+        output.stdout,
+        "while 1\nwhile 3\nuntil\na1\na2\nfile a.txt\nfile b.txt\n"
+    );
+}
+
+#[test]
+fn test_case() {
+    let script = "\
+for word in apple Banana cherry '*'; do
+    case $word in
+        a*) echo \"$word: a\";;
+        [A-Z]*|c*) echo \"$word: upper or c\";;
+        '*') echo star;;
+    esac
+done
+";
+    let output = run(script);
+    assert_eq!(
+        output.stdout,
+        "apple: a\nBanana: upper or c\ncherry: upper or c\nstar\n"
+    );
+}
+
+#[test]
+fn test_functions() {
+    let script = "\
+x=global
+greet() {
+    local x=local
+    echo \"hello $1 $x\"
+    return 3
+}
+greet world
+echo $? $x
+count() { echo $#; }
+count a b c
+fact() {
+    if [ $1 -le 1 ]; then echo 1; return; fi
+    echo $(( $1 * $(fact $(( $1 - 1 ))) ))
+}
+fact 5
+upper() { tr a-z A-Z; }
+echo piped | upper
+";
+    let output = run(script);
+    assert_eq!(
+        output.stdout,
+        "hello world local\n3 global\n3\n120\nPIPED\n"
+    );
+}
+
+#[test]
+fn test_param_error_exits() {
+    // ${X:?}のエラーはスクリプトを終了させる. サブシェルの中ならサブシェルだけが終了する
+    let output = run("\
+(echo ${X:?in subshell}; echo no); echo $?
+echo ${X:?is required}
+echo unreachable
+");
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(
+        output.stderr,
+        "shell: X: in subshell\nshell: X: is required\n"
+    );
+    assert_eq!(output.status, 1);
+}
+
+#[test]
+fn test_command_substitution() {
+    let script = "\
+files=$(ls | sort)
+echo \"$files\"
+echo `echo back` $(echo $(echo nested))
+for w in $(printf 'a b\\nc'); do echo \"<$w>\"; done
+x=$(exit 4); echo $?
+";
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("z"), "").unwrap();
+    let output = run_in(dir.path(), script, &[]);
+    assert_eq!(
+        output.stdout,
+        "script.sh\nz\nback nested\n<a>\n<b>\n<c>\n4\n"
+    );
+}
+
+#[test]
+fn test_arithmetic() {
+    let script = "\
+n=7
+echo $((n * 2 + 1)) $((n % 4)) $(( (n > 5) ? 1 : 0 ))
+let n+=1 'm = n << 1'
+echo $n $m
+echo $((1 / 0))
+echo $?
+";
+    let output = run(script);
+    // 展開に失敗したコマンドは実行されない
+    assert_eq!(output.stdout, "15 3 1\n8 16\n1\n");
+    assert!(output.stderr.contains("division by 0"));
+}
+
+#[test]
+fn test_here_docs() {
+    let script = "\
+name=world
+cat <<EOF
+hello $name
+EOF
+cat <<'EOF'
+hello $name
+EOF
+tr a-z A-Z <<< \"$name\"
+";
+    let output = run(script);
+    assert_eq!(output.stdout, "hello world\nhello $name\nWORLD\n");
+
+    // パイプの容量を超える本文を, パイプラインの途中の関数に渡す
+    let body = format!("{}\n", "x".repeat(99)).repeat(1000);
+    let output = run(&format!("f() {{ cat; }}\nf <<EOF | wc -c\n{}EOF\n", body));
+    assert_eq!(output.stdout.trim(), "100000");
+}
+
+#[test]
+fn test_aliases() {
+    let output = run("alias say='echo said'\nsay hi\nunalias say\nsay 2>/dev/null || echo gone\n");
+    assert_eq!(output.stdout, "said hi\ngone\n");
+}
+
+#[test]
+fn test_builtins() {
+    let script = "\
+echo -n a; echo -e 'b\\tc\\cignored'; echo
+printf '%s=%03d\\n' x 7 y 42
+printf -v padded '[%-4s]' ab; echo \"$padded\"
+[ -f script.sh ] && test ! -d script.sh -a 2 -gt 1 && echo test ok
+[ a = b ]; echo $?
+type cd if; type -t ls
+f() { echo f; }; type -t f
+which sh > /dev/null && echo found sh
+printf 'x=1; set -- \"$@\" more; return 5; x=2\\n' > lib.sh
+. ./lib.sh arg; echo $? $x
+printf 'a  b c\\nline two\\n' > input.txt
+while read first rest; do echo \"[$first][$rest]\"; done < input.txt
+pwd | grep -c /
+exec sh -c 'echo replaced; exit 6'
+echo unreachable
+";
+    let output = run(script);
+    assert_eq!(
+        output.stdout,
+        "ab\tc\nx=007\ny=042\n[ab  ]\ntest ok\n1\ncd is a shell builtin\nif is a shell keyword\n\
+file\nfunction\nfound sh\n5 1\n[a][b c]\n[line][two]\n1\nreplaced\n"
+    );
+    assert_eq!(output.status, 6);
+}
+
+#[test]
+fn test_builtin_pipelines() {
+    let script = "\
+echo hi | wc -c
+alias ll='ls -l'; alias > aliases.txt; cat aliases.txt
+type cd 2>&1 | tr a-z A-Z
+echo err 2> /dev/null >&2
+cd / | true; pwd | grep -c /tmp
+echo 'a b' | read x y; echo \"$x-$y\"
+printf 'first\\nsecond\\n' > lines.txt; read line < lines.txt; echo $line
+seq 20000 | while read n; do echo $n; done | head -1
+exec > out.txt; echo persisted
+";
+    let dir = TempDir::new().unwrap();
+    let output = run_in(dir.path(), script, &[]);
+    assert_eq!(
+        output.stdout,
+        "3\nalias ll='ls -l'\nCD IS A SHELL BUILTIN\n1\na-b\nfirst\n1\n"
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.txt")).unwrap(),
+        "persisted\n"
+    );
+}
+
+#[test]
+fn test_groups() {
+    let script = "\
+x=outer
+(x=inner; cd /; export ADDED=1; echo \"$x $PWD\")
+echo \"$x ${ADDED:-unset}\"; pwd | grep -c /
+{ y=group; cd ..; }; echo $y; cd - > /dev/null
+(echo a; echo b) | wc -l
+{ echo c; echo d; } | tr a-z A-Z
+(echo sub; echo err >&2) > out.txt 2> /dev/null; cat out.txt
+{ echo g; } >> out.txt; cat out.txt
+echo piped | (read v; echo \"got $v\")
+(exit 7); echo $?
+(
+  echo multi
+)
+(exit 3)
+";
+    let output = run(script);
+    assert_eq!(
+        output.stdout,
+        "inner /\nouter unset\n1\ngroup\n2\nC\nD\nsub\nsub\ng\ngot piped\n7\nmulti\n"
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 3);
+}
+
+#[test]
+fn test_script_from_stdin() {
+    // スクリプトの続きの行はreadや子プロセスが読めるように, シェルは自分の1行の分だけを読む
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"read x\nhello\necho got $x\nsh -c 'read y; echo child $y'\nline\necho after\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "got hello\nchild line\nafter\n"
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn test_command_string() {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .args(["-c", "echo $0 $1; exit 5", "name", "arg"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "name arg\n");
+    assert_eq!(output.status.code(), Some(5));
+}